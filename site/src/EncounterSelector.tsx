@@ -1,5 +1,6 @@
 import {
   Accessor,
+  For,
   JSX,
   ParentProps,
   Show,
@@ -122,6 +123,31 @@ const PageLayout = styled("div", {
   },
 });
 
+type SkippedRange = { start: number; end: number; reason: string };
+
+function SkippedWarning(props: { data?: SavedVariablesRef }): JSX.Element {
+  const skipped = (): SkippedRange[] => props.data?.skipped() ?? [];
+
+  return (
+    <Show when={skipped().length > 0}>
+      <details>
+        <summary>
+          Parts of this file could not be read and were skipped. Some recordings may be missing.
+        </summary>
+        <ul>
+          <For each={skipped()}>
+            {(range) => (
+              <li>
+                Bytes {range.start}&ndash;{range.end}: {range.reason}
+              </li>
+            )}
+          </For>
+        </ul>
+      </details>
+    </Show>
+  );
+}
+
 export default function EncounterSelector(props: ParentProps): JSX.Element {
   const { store } = useSavedVariables();
   const [recording, setRecording] = createSignal<RecordingRef | undefined>();
@@ -137,6 +163,7 @@ export default function EncounterSelector(props: ParentProps): JSX.Element {
           selectedRecording: recording,
         }}
       >
+        <SkippedWarning data={store?.().data} />
         <PageLayout>
           <EncounterList data={store?.().data} onClick={setSelection} />
           <div>{props.children}</div>
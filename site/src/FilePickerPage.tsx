@@ -34,7 +34,7 @@ export default function FilePickerPage() {
           accept=".lua"
         />
         {store?.().success === false && (
-          <div>{store?.().error?.toString() ?? "Unable to read data"}</div>
+          <pre>{store?.().error?.toString() ?? "Unable to read data"}</pre>
         )}
       </SelectForm>
     </CenteringContainer>
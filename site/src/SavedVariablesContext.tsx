@@ -20,11 +20,11 @@ type Store = {
 export function SavedVariablesProvider(props: ParentProps) {
   const [store, setStore] = createSignal<Store>({});
   function load(file: File) {
-    file
-      .text()
-      .then((data) => {
-        setStore(savedVariables.parse(data));
-      })
+    savedVariables
+      .parseStream(file.stream(), (data) => setStore({ success: true, data }))
+      // the whole file is needed to recover from errors
+      .catch(() => file.arrayBuffer().then((data) => savedVariables.parse(new Uint8Array(data))))
+      .then((result) => setStore(result))
       .catch((err) =>
         setStore({
           success: false,
@@ -92,11 +92,51 @@ export type ParseResult =
     }
   | { success: false; error: unknown };
 
-export function parse(data: string): ParseResult {
+export function parse(data: string | Uint8Array): ParseResult {
   try {
-    const result = parser.parse_saved_variables(data);
+    const result =
+      typeof data === "string"
+        ? parser.parse_saved_variables(data)
+        : parser.parse_saved_variables_bytes(data);
     return { success: true, data: result };
   } catch (error) {
     return { success: false, error };
   }
 }
+
+/**
+ * Parse a file as it is read, calling `onProgress` whenever more recordings are available, and with
+ * the result once the whole file is read. Fails if the file is broken, in which case `parse` can
+ * still recover what's readable.
+ *
+ * Each snapshot passed to `onProgress` is freed once the next one is passed, so it must not be kept
+ * past that.
+ */
+export async function parseStream(
+  stream: ReadableStream<Uint8Array>,
+  onProgress: (data: parser.SavedVariablesRef) => void
+): Promise<ParseResult> {
+  const parsing = new parser.SavedVariablesStream();
+  const reader = stream.getReader();
+  let snapshot: parser.SavedVariablesRef | undefined;
+  const progress = (next: parser.SavedVariablesRef) => {
+    onProgress(next);
+    snapshot?.free();
+    snapshot = next;
+  };
+  try {
+    for (;;) {
+      const { done, value } = await reader.read();
+      if (done) {
+        const data = parsing.finish();
+        progress(data);
+        return { success: true, data };
+      }
+      if (parsing.push(value) > 0) {
+        progress(parsing.snapshot());
+      }
+    }
+  } finally {
+    parsing.free();
+  }
+}
@@ -1,3 +1,5 @@
+// only the types' schema is needed here, not their parsing helpers
+#[allow(dead_code)]
 #[path = "src/parser/types.rs"]
 mod types;
 
@@ -1,7 +1,7 @@
 /// Support for decoding/decompressing data that has been compressed with LibDeflate in World of Warcraft.
 use std::io::prelude::*;
 
-use flate2::write::DeflateDecoder;
+use flate2::read::DeflateDecoder;
 
 const PRINT_DECODING_TABLE: &[u8] = &[
     0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
@@ -31,6 +31,8 @@ pub enum DecompressionError {
     InvalidPrintByte(u8),
     #[error("Unable to decode with DEFLATE: {0}")]
     DeflateError(std::io::Error),
+    #[error("Decompressed data is larger than {0} bytes")]
+    TooLarge(usize),
 }
 
 /// Port of LibDeflate:DecodeForPrint
@@ -74,16 +76,22 @@ pub fn decode_for_print(input: &str) -> Result<Vec<u8>, DecompressionError> {
 }
 
 pub fn decompress(input: &str) -> Result<Vec<u8>, DecompressionError> {
+    decompress_with_limit(input, usize::MAX)
+}
+
+/// Like [`decompress`], but failing if the data decompresses to more than `max_bytes`. A few bytes of
+/// DEFLATE can expand to gigabytes.
+pub fn decompress_with_limit(input: &str, max_bytes: usize) -> Result<Vec<u8>, DecompressionError> {
     let decoded = decode_for_print(input)?;
 
     let mut buffer = Vec::new();
-    let mut deflater = DeflateDecoder::new(buffer);
-    deflater
-        .write_all(&decoded[..])
-        .map_err(DecompressionError::DeflateError)?;
-    buffer = deflater
-        .finish()
+    DeflateDecoder::new(&decoded[..])
+        .take((max_bytes as u64).saturating_add(1))
+        .read_to_end(&mut buffer)
         .map_err(DecompressionError::DeflateError)?;
+    if buffer.len() > max_bytes {
+        return Err(DecompressionError::TooLarge(max_bytes));
+    }
 
     Ok(buffer)
 }
@@ -5,8 +5,7 @@
 /// enabled by default.
 use std::{
     borrow::Cow,
-    cell::RefCell,
-    collections::HashMap,
+    cell::{Cell, RefCell},
     fmt::{Debug, Display},
     ops::RangeFrom,
     rc::Rc,
@@ -27,7 +26,8 @@ use nom::{
 use num_derive::FromPrimitive;
 use num_traits::cast::FromPrimitive;
 
-use serde_savedvariables::{Table, Value};
+use serde_savedvariables::{Budget, Key, Map, Table, Value};
+pub use serde_savedvariables::{LimitExceeded, Limits};
 
 #[cfg(feature = "libdeflate")]
 pub mod deflate;
@@ -44,32 +44,41 @@ struct ValueRefTable<T> {
     // by_value: HashMap<T, usize>,
 }
 
-#[derive(Clone, Debug)]
+/// What reading a table cost against the limits, in values and bytes. Every reference to the table
+/// makes a copy, which costs the same again.
+type Cost = (usize, usize);
+
+#[derive(Debug)]
 struct State<'a> {
     string_refs: RefCell<ValueRefTable<Cow<'a, str>>>,
-    table_refs: RefCell<ValueRefTable<Table<'a>>>,
+    table_refs: RefCell<ValueRefTable<(Table<'a>, Cost)>>,
+    budget: Budget,
+    /// The limit that stopped parsing, if any. nom errors can't carry it, so it is kept here.
+    exceeded: Cell<Option<LimitExceeded>>,
 }
 
 impl<'a> State<'a> {
-    fn new<'b>() -> State<'b> {
+    fn new<'b>(limits: Limits) -> State<'b> {
         State {
             string_refs: RefCell::new(ValueRefTable { by_index: vec![] }),
             table_refs: RefCell::new(ValueRefTable { by_index: vec![] }),
+            budget: Budget::new(limits),
+            exceeded: Cell::new(None),
         }
     }
 
     fn add_str_ref(&self, value: Cow<'a, str>) {
         self.string_refs.borrow_mut().by_index.push(value);
     }
-    fn add_table_ref(&self, value: Table<'a>) {
-        self.table_refs.borrow_mut().by_index.push(value);
+    fn add_table_ref(&self, value: Table<'a>, cost: Cost) {
+        self.table_refs.borrow_mut().by_index.push((value, cost));
     }
 
     fn get_str_ref(&self, key: usize) -> Option<Cow<'a, str>> {
         self.string_refs.borrow().by_index.get(key - 1).cloned()
     }
 
-    fn get_table_ref(&self, key: usize) -> Option<Table<'a>> {
+    fn get_table_ref(&self, key: usize) -> Option<(Table<'a>, Cost)> {
         self.table_refs.borrow().by_index.get(key - 1).cloned()
     }
 }
@@ -81,12 +90,24 @@ struct ParserState<'a> {
 }
 
 impl<'a> ParserState<'a> {
-    fn new(input: Bytes<'_>) -> ParserState<'_> {
+    fn new(input: Bytes<'_>, limits: Limits) -> ParserState<'_> {
         ParserState {
             input,
-            state: Rc::new(State::new()),
+            state: Rc::new(State::new(limits)),
         }
     }
+
+    /// Stop parsing here if `result` went over a limit.
+    fn check(&self, result: Result<(), LimitExceeded>) -> Result<(), nom::Err<VerboseError<Self>>> {
+        result.map_err(|exceeded| {
+            self.state.exceeded.set(Some(exceeded));
+            nom::Err::Failure(VerboseError::from_external_error(
+                self.clone(),
+                ErrorKind::TooLarge,
+                exceeded,
+            ))
+        })
+    }
 }
 
 impl<'a> InputIter for ParserState<'a> {
@@ -255,44 +276,48 @@ fn array(entry_count: u32) -> impl FnMut(ParserState) -> IResult<Vec<Value>> {
     move |input| many_m_n(entry_count as usize, entry_count as usize, any_object)(input)
 }
 
+/// Size of a serialized float: a header byte and a big-endian double.
+const FLOAT_BYTES: usize = 9;
+
 fn float_array(entry_count: u32) -> impl FnMut(ParserState) -> IResult<Value> {
     move |input| {
-        fold_many_m_n(
+        // the count comes from the input, so don't trust it further than the input goes
+        let capacity = (entry_count as usize).min(input.input_len() / FLOAT_BYTES);
+        let (rest, array) = fold_many_m_n(
             entry_count as usize,
             entry_count as usize,
             deserialize_float,
-            || Vec::with_capacity(entry_count as usize),
+            || Vec::with_capacity(capacity),
             |mut vec, value| {
                 vec.push(value);
                 vec
             },
         )
-        .map(|v| Value::Table(Table::FloatArray(v)))
-        .parse(input)
+        .parse(input.clone())?;
+        input.check(
+            input
+                .state
+                .budget
+                .charge(array.len(), array.len() * std::mem::size_of::<f64>()),
+        )?;
+        Ok((rest, Value::Table(Table::FloatArray(array.into()))))
     }
 }
 
 /// Read `count` keys from a table into a hashmap.
-fn table(entry_count: u32) -> impl FnMut(ParserState) -> IResult<HashMap<Cow<str>, Value>> {
+fn table(entry_count: u32) -> impl FnMut(ParserState) -> IResult<Map<Key, Value>> {
     move |input| {
         let res = fold_many_m_n(
             entry_count as usize,
             entry_count as usize,
             pair(
                 context(
-                    "found table in key location in non-array table",
-                    map_res(any_object, |v| match v {
-                        Value::String(s) => Ok(s),
-                        Value::Int(v) => Ok(Cow::Owned(v.to_string())),
-                        Value::Float(v) => Ok(Cow::Owned(v.to_string())),
-                        Value::Bool(b) => Ok(Cow::Owned(b.to_string())),
-                        Value::Nil => Ok(Cow::Borrowed("nil")),
-                        Value::Table(_actual) => Err("found table in table key location"),
-                    }),
+                    "found nil or table in table key location",
+                    map_res(any_object, Key::try_from),
                 ),
                 any_object,
             ),
-            HashMap::new,
+            Map::new,
             |mut map, (k, v)| {
                 map.insert(k, v);
                 map
@@ -321,13 +346,23 @@ fn mixed_table(
     }
 }
 
+/// Read a table, keeping a copy of it for later references. This is also where nesting is counted
+/// against the depth limit.
 fn store_table_ref<'a>(
     mut parser: impl FnMut(ParserState<'a>) -> IResult<Value<'a>>,
 ) -> impl FnMut(ParserState<'a>) -> IResult<Value<'a>> {
     move |input| {
-        let (output, result) = parser(input)?;
+        let state = input.state.clone();
+        input.check(state.budget.enter())?;
+        let (elements, bytes) = state.budget.used();
+        let result = parser(input);
+        state.budget.leave();
+
+        let (output, result) = result?;
         if let Value::Table(table) = &result {
-            output.state.add_table_ref(table.clone());
+            let (after_elements, after_bytes) = state.budget.used();
+            let cost = (after_elements - elements, after_bytes - bytes);
+            output.state.add_table_ref(table.clone(), cost);
         }
         Ok((output, result))
     }
@@ -340,7 +375,11 @@ fn table_ref(key: usize) -> impl FnMut(ParserState) -> IResult<Value> {
             ErrorKind::MapOpt,
             DeserializationError::MissingRef(key),
         ))),
-        Some(table) => Ok((input, Value::Table(table.clone()))),
+        Some((table, (elements, bytes))) => {
+            // the copy can be as large as the original, and references can be nested to blow up
+            input.check(input.state.budget.charge(elements, bytes))?;
+            Ok((input, Value::Table(table)))
+        }
     }
 }
 
@@ -539,7 +578,7 @@ fn deserialize_large_object(input: ParserState) -> IResult<Value> {
 }
 
 fn any_object(input: ParserState) -> IResult<Value> {
-    context(
+    let (rest, value) = context(
         "no object type matched",
         alt((
             context("parsing packed u7", deserialize_ushort),
@@ -547,7 +586,13 @@ fn any_object(input: ParserState) -> IResult<Value> {
             context("parsing small object", deserialize_small_object),
             context("parsing large object", deserialize_large_object),
         )),
-    )(input)
+    )(input.clone())?;
+    let budget = &input.state.budget;
+    input.check(match &value {
+        Value::String(s) => budget.string(s.len(), false),
+        _ => budget.element(),
+    })?;
+    Ok((rest, value))
 }
 
 fn deserialize_internal(input: ParserState) -> IResult<Value> {
@@ -564,8 +609,10 @@ pub enum DeserializationError {
     MissingRef(usize),
     #[error("Failed to parse serialized data. {0}")]
     GenericParseError(SerializeParseError),
-    #[error("Failed to deserialize from SavedVariables format.")]
+    #[error("Failed to deserialize from SavedVariables format. {0}")]
     SavedVariablesError(#[from] serde_savedvariables::ParseError),
+    #[error("Serialized data is too large: {0}")]
+    LimitExceeded(#[from] LimitExceeded),
     #[cfg(feature = "libdeflate")]
     #[error("Unable to decompress data. {0}")]
     DecompressionError(#[from] deflate::DecompressionError),
@@ -585,35 +632,55 @@ impl Display for SerializeParseError {
     }
 }
 
-fn deserialize<'a: 'b, 'b>(input: &'a [u8]) -> Result<Value<'b>, SerializeParseError> {
-    let state = ParserState::new(input);
+#[cfg(test)]
+fn deserialize(input: &[u8]) -> Result<Value<'_>, DeserializationError> {
+    deserialize_with(input, Limits::default())
+}
+
+fn deserialize_with(input: &[u8], limits: Limits) -> Result<Value<'_>, DeserializationError> {
+    let state = ParserState::new(input, limits);
+    let shared = state.state.clone();
     match deserialize_internal(state) {
-        Err(err) => match err {
-            nom::Err::Incomplete(_) => {
+        Err(err) => match (err, shared.exceeded.get()) {
+            (nom::Err::Incomplete(_), _) => {
                 unreachable!("cannot reach this point due to complete combinator")
             }
-            nom::Err::Failure(err) | nom::Err::Error(err) => Err(SerializeParseError {
-                repr: err
-                    .errors
-                    .into_iter()
-                    .map(|(inner, kind)| {
-                        format!("{:?} in byte {}", kind, input.offset(inner.input))
-                    })
-                    .collect(),
-            }),
+            (_, Some(exceeded)) => Err(exceeded.into()),
+            (nom::Err::Failure(err) | nom::Err::Error(err), None) => Err(
+                DeserializationError::GenericParseError(SerializeParseError {
+                    repr: err
+                        .errors
+                        .into_iter()
+                        .map(|(inner, kind)| {
+                            format!("{:?} in byte {}", kind, input.offset(inner.input))
+                        })
+                        .collect(),
+                }),
+            ),
         },
         Ok((_, value)) => Ok(value),
     }
 }
 
 /// Deserialize data from a LibDeflate string encoded with EncodeForPrint.
+///
+/// The data has to be decompressed first, so nothing can be borrowed from `input`. Use
+/// [`deflate::decompress`] and [`from_bytes`] to borrow strings from the decompressed data instead.
 #[cfg(feature = "libdeflate")]
-pub fn from_str<'de, T: serde::de::Deserialize<'de>>(
+pub fn from_str<T: serde::de::DeserializeOwned>(input: &str) -> Result<T, DeserializationError> {
+    from_str_with(input, Limits::default())
+}
+
+/// Like [`from_str`], with `limits` instead of the default ones. The decompressed data may be at
+/// most `limits.max_allocation` bytes.
+#[cfg(feature = "libdeflate")]
+pub fn from_str_with<T: serde::de::DeserializeOwned>(
     input: &str,
+    limits: Limits,
 ) -> Result<T, DeserializationError> {
-    let decompressed = deflate::decompress(input)?;
+    let decompressed = deflate::decompress_with_limit(input, limits.max_allocation)?;
 
-    from_bytes(&decompressed)
+    from_bytes_with(&decompressed, limits)
 }
 
 /// Deserialize data from a raw byte array. Note that the strings produced by LibSerialize are NOT
@@ -622,14 +689,22 @@ pub fn from_str<'de, T: serde::de::Deserialize<'de>>(
 ///
 /// It is strongly encouraged to encode your data after serialization. This method exists to support
 /// use cases that do not use LibDeflate to handle the encoding.
+///
+/// Strings are borrowed from `input` where the target type allows it.
 pub fn from_bytes<'de, T: serde::de::Deserialize<'de>>(
-    input: &[u8],
+    input: &'de [u8],
+) -> Result<T, DeserializationError> {
+    from_bytes_with(input, Limits::default())
+}
+
+/// Like [`from_bytes`], with `limits` instead of the default ones.
+pub fn from_bytes_with<'de, T: serde::de::Deserialize<'de>>(
+    input: &'de [u8],
+    limits: Limits,
 ) -> Result<T, DeserializationError> {
     use serde::de::IntoDeserializer;
 
-    let deserializer = deserialize(input)
-        .map_err(DeserializationError::GenericParseError)?
-        .into_deserializer();
+    let deserializer = deserialize_with(input, limits)?.into_deserializer();
 
     Ok(T::deserialize(deserializer)?)
 }
@@ -638,10 +713,9 @@ pub fn from_bytes<'de, T: serde::de::Deserialize<'de>>(
 mod test {
     use std::borrow::Cow;
 
-    use map_macro::hash_map;
     use pretty_assertions::assert_eq;
 
-    use super::{Table, Value};
+    use super::{Key, Map, Table, Value};
 
     #[test]
     fn test_deserialize_int() {
@@ -699,17 +773,109 @@ mod test {
         let result = super::deserialize(&data).unwrap();
         assert_eq!(
             result,
-            Value::Table(Table::Named(hash_map! {
-              Cow::Borrowed("skew") => Value::Float(-0.23456),
-              Cow::Borrowed("mean") => Value::Float(0.05001),
-              Cow::Borrowed("samples") => Value::Table(Table::Array(vec![Value::Int(1), Value::Int(2), Value::Int(3), Value::Int(4)])),
-              Cow::Borrowed("quantiles") => Value::Table(Table::Named(hash_map! {
-                Cow::Borrowed("0.5") => Value::Float(0.05),
-                Cow::Borrowed("0.75") => Value::Float(0.06),
-                Cow::Borrowed("0.95") => Value::Float(0.08),
-                Cow::Borrowed("0.99") => Value::Float(0.1),
-              }))
-            }))
+            Value::Table(Table::Named(Map::from([
+                (Key::String(Cow::Borrowed("skew")), Value::Float(-0.23456)),
+                (Key::String(Cow::Borrowed("mean")), Value::Float(0.05001)),
+                (
+                    Key::String(Cow::Borrowed("samples")),
+                    Value::Table(Table::Array(vec![
+                        Value::Int(1),
+                        Value::Int(2),
+                        Value::Int(3),
+                        Value::Int(4)
+                    ]))
+                ),
+                (
+                    Key::String(Cow::Borrowed("quantiles")),
+                    Value::Table(Table::Named(Map::from([
+                        (Key::String(Cow::Borrowed("0.5")), Value::Float(0.05)),
+                        (Key::String(Cow::Borrowed("0.75")), Value::Float(0.06)),
+                        (Key::String(Cow::Borrowed("0.95")), Value::Float(0.08)),
+                        (Key::String(Cow::Borrowed("0.99")), Value::Float(0.1)),
+                    ])))
+                ),
+            ])))
         )
     }
+
+    #[test]
+    fn test_deserialize_enum() {
+        #[derive(serde::Deserialize, Debug, PartialEq)]
+        #[serde(rename_all = "lowercase")]
+        enum Test {
+            Foo,
+            Bar(u32),
+        }
+
+        let data = [0x01, 0x32, 0x66, 0x6f, 0x6f];
+        let result: Test = super::from_bytes(&data).unwrap();
+        assert_eq!(result, Test::Foo);
+
+        let data = [0x01, 0x16, 0x32, 0x62, 0x61, 0x72, 0x0b];
+        let result: Test = super::from_bytes(&data).unwrap();
+        assert_eq!(result, Test::Bar(5));
+    }
+
+    #[test]
+    fn test_deserialize_nil_holes() {
+        // { 1, nil, 3 }
+        let data = [0x01, 0x3a, 0x03, 0x00, 0x07];
+        let result: Vec<Option<u32>> = super::from_bytes(&data).unwrap();
+        assert_eq!(result, vec![Some(1), None, Some(3)]);
+
+        // { [1] = 5, [3] = 7 }
+        let data = [0x01, 0x26, 0x03, 0x0b, 0x07, 0x0f];
+        let result: Vec<Option<u32>> = super::from_bytes(&data).unwrap();
+        assert_eq!(result, vec![Some(5), None, Some(7)]);
+    }
+
+    #[test]
+    fn test_deserialize_borrowed_str() {
+        // { bar = "foo" }
+        let data = [0x01, 0x16, 0x32, b'b', b'a', b'r', 0x32, b'f', b'o', b'o'];
+        let result: std::collections::HashMap<&str, &str> = super::from_bytes(&data).unwrap();
+        assert_eq!(result["bar"], "foo");
+    }
+
+    #[test]
+    fn test_deserialize_limits() {
+        use super::{DeserializationError, LimitExceeded, Limits};
+
+        // { { { ... } } }, nested far deeper than the stack allows
+        let mut data = vec![0x01];
+        data.extend([0x1a; 100_000]);
+        data.push(0x0b);
+        assert!(matches!(
+            super::deserialize(&data),
+            Err(DeserializationError::LimitExceeded(LimitExceeded::Depth(
+                100
+            )))
+        ));
+
+        // an array claiming 2^24 - 1 entries, with none of them present
+        let data = [0x01, 0xb0, 0xff, 0xff, 0xff];
+        assert!(matches!(
+            super::deserialize(&data),
+            Err(DeserializationError::GenericParseError(_))
+        ));
+
+        // an array of 15 ints, followed by 14 references to it
+        let mut data = vec![0x01, 0xfa, 0xfa];
+        data.extend([0x03; 15]);
+        for _ in 0..14 {
+            data.extend([0xe8, 0x01]);
+        }
+        let limits = |max_elements| Limits {
+            max_elements,
+            ..Default::default()
+        };
+        let result: Vec<Vec<u32>> = super::from_bytes_with(&data, limits(300)).unwrap();
+        assert_eq!(result, vec![vec![1; 15]; 15]);
+        assert!(matches!(
+            super::from_bytes_with::<Vec<Vec<u32>>>(&data, limits(100)),
+            Err(DeserializationError::LimitExceeded(
+                LimitExceeded::Elements(100)
+            ))
+        ));
+    }
 }
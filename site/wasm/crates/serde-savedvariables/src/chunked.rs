@@ -0,0 +1,458 @@
+//! Reading the elements of an array from input that arrives in chunks.
+
+use std::io::Read;
+
+use crate::{recover::TableSearch, ParseError, SyntaxError};
+
+/// The source text of one element of the array, copied out of the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Element {
+    /// Byte offset of the element in the whole input.
+    pub offset: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    /// Looking for the start of the array.
+    Searching,
+    Elements,
+    /// The array has been closed. Anything after it is ignored.
+    Done,
+}
+
+/// Where the scanner is, as far as telling braces in code from braces in text is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Scan {
+    Code,
+    Quoted {
+        quote: u8,
+        escaped: bool,
+    },
+    /// A long string or long comment, closed by `]`, `level` times `=` and `]`.
+    Long {
+        level: usize,
+    },
+    LineComment,
+}
+
+/// Match the opening of a long bracket at the start of `input`, returning its level and length.
+/// `None` means that more input is needed to tell, and `Some(None)` that it isn't one.
+fn long_bracket(input: &[u8]) -> Option<Option<(usize, usize)>> {
+    let level = input[1..].iter().take_while(|&&b| b == b'=').count();
+    match input.get(level + 1) {
+        None => None,
+        Some(b'[') => Some(Some((level, level + 2))),
+        Some(_) => Some(None),
+    }
+}
+
+/// Match the closing long bracket of `level` at the start of `input`, with the same conventions as
+/// [`long_bracket`].
+fn long_bracket_end(input: &[u8], level: usize) -> Option<bool> {
+    let equals = input[1..]
+        .iter()
+        .take(level)
+        .take_while(|&&b| b == b'=')
+        .count();
+    match input.get(equals + 1) {
+        None => None,
+        Some(b']') => Some(equals == level),
+        Some(_) => Some(false),
+    }
+}
+
+impl Scan {
+    /// Move past the start of `rest`, returning how many bytes that took, or `None` if more input
+    /// is needed to tell. Code is passed a byte at a time, and each byte that is passed from `Code`
+    /// back to `Code` is a byte of code.
+    pub(crate) fn advance(&mut self, rest: &[u8]) -> Option<usize> {
+        let mut len = 1;
+        match *self {
+            Scan::Code => match rest[0] {
+                quote @ (b'"' | b'\'') => {
+                    *self = Scan::Quoted {
+                        quote,
+                        escaped: false,
+                    }
+                }
+                b'[' => {
+                    if let Some((level, bracket)) = long_bracket(rest)? {
+                        *self = Scan::Long { level };
+                        len = bracket;
+                    }
+                }
+                b'-' if *rest.get(1)? == b'-' => {
+                    match rest.get(2).map(|&b| (b, long_bracket(&rest[2..]))) {
+                        None | Some((b'[', None)) => return None,
+                        Some((b'[', Some(Some((level, bracket))))) => {
+                            *self = Scan::Long { level };
+                            len = bracket + 2;
+                        }
+                        Some(_) => {
+                            *self = Scan::LineComment;
+                            len = 2;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            Scan::Quoted { quote, escaped } => {
+                *self = match rest[0] {
+                    _ if escaped => Scan::Quoted {
+                        quote,
+                        escaped: false,
+                    },
+                    b'\\' => Scan::Quoted {
+                        quote,
+                        escaped: true,
+                    },
+                    b if b == quote => Scan::Code,
+                    _ => *self,
+                }
+            }
+            Scan::Long { level } => {
+                if rest[0] == b']' && long_bracket_end(rest, level)? {
+                    *self = Scan::Code;
+                    len = level + 2;
+                }
+            }
+            Scan::LineComment => {
+                if rest[0] == b'\n' {
+                    *self = Scan::Code;
+                }
+            }
+        }
+        Some(len)
+    }
+}
+
+/// Finds the elements of the array at `path` in input that is fed in a chunk at a time, and hands
+/// out each one as soon as its closing brace arrives.
+///
+/// Elements must be tables. They are only scanned for their extent here, so they still have to be
+/// parsed, e.g. with [`crate::from_str`]. Their text is copied out of the buffered input, which is
+/// dropped as it is used up. The array is found like [`crate::recover_array_from_str`] does, and
+/// only as much input as a partial match needs is kept while looking for it.
+pub struct ChunkedArray {
+    path: Vec<String>,
+    table: TableSearch,
+    buffer: Vec<u8>,
+    /// Bytes and line breaks already dropped from the front of `buffer`, and the characters
+    /// dropped from its first line.
+    consumed: usize,
+    consumed_lines: usize,
+    consumed_columns: usize,
+    stage: Stage,
+    scan: Scan,
+    /// How far `buffer` has been scanned.
+    pos: usize,
+    depth: usize,
+    /// Start of the element being scanned.
+    start: usize,
+}
+
+impl ChunkedArray {
+    /// Read the array at `path`: the name of a global followed by the keys leading to the array.
+    pub fn new(path: &[&str]) -> ChunkedArray {
+        ChunkedArray {
+            path: path.iter().map(|key| key.to_string()).collect(),
+            table: TableSearch::new(path),
+            buffer: Vec::new(),
+            consumed: 0,
+            consumed_lines: 0,
+            consumed_columns: 0,
+            stage: Stage::Searching,
+            scan: Scan::Code,
+            pos: 0,
+            depth: 0,
+            start: 0,
+        }
+    }
+
+    /// Add the next chunk of input, returning the elements that it completed.
+    ///
+    /// Input before the array must be valid UTF-8, and a syntax error is returned at the first
+    /// byte that isn't. Elements are copied out with invalid sequences replaced, and are checked
+    /// when they are parsed.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<Vec<Element>, ParseError> {
+        if self.stage == Stage::Done {
+            return Ok(vec![]);
+        }
+        self.buffer.extend_from_slice(chunk);
+
+        if self.stage == Stage::Searching {
+            self.search()?;
+            if self.stage == Stage::Searching {
+                return Ok(vec![]);
+            }
+        }
+
+        let mut elements = vec![];
+        while let Some(end) = self.scan()? {
+            elements.push(Element {
+                offset: self.consumed + self.start,
+                text: String::from_utf8_lossy(&self.buffer[self.start..end]).into_owned(),
+            });
+            // keep the rest of the line, so that error positions stay accurate
+            let line_start = self.buffer[..end]
+                .iter()
+                .rposition(|&b| b == b'\n')
+                .map_or(0, |ix| ix + 1);
+            self.drop_front(line_start);
+        }
+        if self.stage == Stage::Elements && self.depth == 0 {
+            let line_start = self.buffer[..self.pos]
+                .iter()
+                .rposition(|&b| b == b'\n')
+                .map_or(0, |ix| ix + 1);
+            self.drop_front(line_start);
+        }
+        Ok(elements)
+    }
+
+    /// Signal the end of the input, checking that the whole array was read.
+    pub fn finish(&mut self) -> Result<(), ParseError> {
+        match self.stage {
+            Stage::Searching => Err(ParseError::MissingGlobal(self.path.join("."))),
+            Stage::Elements => Err(ParseError::UnexpectedEnd(self.path.join("."))),
+            Stage::Done => Ok(()),
+        }
+    }
+
+    /// Feed all of `reader` through, passing each element to `on_element`.
+    pub fn read_from<R: Read>(
+        &mut self,
+        mut reader: R,
+        mut on_element: impl FnMut(Element),
+    ) -> Result<(), ParseError> {
+        let mut chunk = vec![0; 64 * 1024];
+        loop {
+            match reader.read(&mut chunk) {
+                Ok(0) => return self.finish(),
+                Ok(n) => self
+                    .feed(&chunk[..n])?
+                    .into_iter()
+                    .for_each(&mut on_element),
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    fn drop_front(&mut self, len: usize) {
+        let dropped = &self.buffer[..len];
+        let (line, lines) = match dropped.iter().rposition(|&b| b == b'\n') {
+            Some(ix) => (
+                &dropped[ix + 1..],
+                dropped.iter().filter(|&&b| b == b'\n').count(),
+            ),
+            None => (dropped, 0),
+        };
+        // count characters by the bytes that start them
+        let columns = line.iter().filter(|&&b| b & 0xc0 != 0x80).count();
+        self.consumed_columns = columns + if lines == 0 { self.consumed_columns } else { 0 };
+        self.consumed += len;
+        self.consumed_lines += lines;
+        self.buffer.drain(..len);
+        self.pos -= len.min(self.pos);
+        self.start -= len.min(self.start);
+    }
+
+    fn error(&self, offset: usize, expected: &str) -> ParseError {
+        let text = String::from_utf8_lossy(&self.buffer);
+        let mut err = SyntaxError::new(&text, offset, expected.to_string());
+        if err.line == 1 {
+            err.column += self.consumed_columns;
+        }
+        err.offset += self.consumed;
+        err.line += self.consumed_lines;
+        err.into()
+    }
+
+    /// Look for the rest of `path` in the buffered input, dropping what has been searched.
+    fn search(&mut self) -> Result<(), ParseError> {
+        let text = match std::str::from_utf8(&self.buffer) {
+            Ok(text) => text,
+            // the chunk may end partway through a character
+            Err(err) if err.error_len().is_none() => {
+                std::str::from_utf8(&self.buffer[..err.valid_up_to()]).unwrap()
+            }
+            Err(err) => return Err(self.error(err.valid_up_to(), "valid UTF-8")),
+        };
+        match self.table.search(text) {
+            Ok(start) => {
+                self.drop_front(start);
+                self.stage = Stage::Elements;
+            }
+            Err(searched) => self.drop_front(searched),
+        }
+        Ok(())
+    }
+
+    /// Scan the buffered input until the end of the next element, returning where it ends. Returns
+    /// `None` if more input is needed.
+    fn scan(&mut self) -> Result<Option<usize>, ParseError> {
+        while self.stage == Stage::Elements && self.pos < self.buffer.len() {
+            let rest = &self.buffer[self.pos..];
+            let code = self.scan == Scan::Code;
+            let len = match self.scan.advance(rest) {
+                Some(len) => len,
+                None => return Ok(None),
+            };
+            // check the byte that starts a string or comment too, so that a string can't pass for
+            // an element
+            if code {
+                match rest[0] {
+                    b'{' => {
+                        if self.depth == 0 {
+                            self.start = self.pos;
+                        }
+                        self.depth += 1;
+                    }
+                    b'}' if self.depth == 0 => self.stage = Stage::Done,
+                    b'}' => {
+                        self.depth -= 1;
+                        if self.depth == 0 {
+                            self.pos += 1;
+                            return Ok(Some(self.pos));
+                        }
+                    }
+                    // comments may follow an element
+                    b'-' if self.scan != Scan::Code => {}
+                    b if self.depth == 0
+                        && !(b.is_ascii_whitespace() || b == b',' || b == b';') =>
+                    {
+                        return Err(self.error(self.pos, "a table or `}`"))
+                    }
+                    _ => {}
+                }
+            }
+            self.pos += len;
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::ChunkedArray;
+    use crate::ParseError;
+
+    const INPUT: &str = "Storage = {\n\t[\"other\"] = { 1 },\n\t[\"recordings\"] = {\n\t\t{\n\t\t\t[\"name\"] = \"a}\",\n\t\t\t[\"data\"] = [==[ } ]] ]==], -- }\n\t\t}, -- [1]\n\t\t{\n\t\t\t[\"name\"] = 'b\\'}',\n\t\t\t--[[ } ]] [\"n\"] = -1,\n\t\t}, -- [2]\n\t},\n}\n";
+
+    #[test]
+    fn chunked_elements() {
+        let elements: Vec<_> = (1..=INPUT.len())
+            .map(|size| {
+                let mut array = ChunkedArray::new(&["Storage", "recordings"]);
+                let mut elements = vec![];
+                for chunk in INPUT.as_bytes().chunks(size) {
+                    elements.extend(array.feed(chunk).unwrap());
+                }
+                array.finish().unwrap();
+                elements
+            })
+            .collect();
+
+        let first = INPUT.find("{\n\t\t\t[\"name\"] = \"a").unwrap();
+        let second = INPUT.find("{\n\t\t\t[\"name\"] = 'b").unwrap();
+        assert_eq!(elements[0].len(), 2);
+        assert_eq!(elements[0][0].offset, first);
+        assert_eq!(
+            elements[0][0].text,
+            &INPUT[first..INPUT.find("}, -- [1]").unwrap() + 1]
+        );
+        assert_eq!(elements[0][1].offset, second);
+        assert_eq!(
+            elements[0][1].text,
+            &INPUT[second..INPUT.find("}, -- [2]").unwrap() + 1]
+        );
+        for result in &elements {
+            assert_eq!(result, &elements[0]);
+        }
+    }
+
+    #[test]
+    fn chunked_read() {
+        let mut array = ChunkedArray::new(&["Storage", "recordings"]);
+        let mut names = vec![];
+        array
+            .read_from(INPUT.as_bytes(), |element| {
+                let value = crate::from_str::<crate::Value>(&element.text).unwrap();
+                names.push(value["name"].as_str().unwrap().to_string());
+            })
+            .unwrap();
+        assert_eq!(names, vec!["a}", "b'}"]);
+    }
+
+    #[test]
+    fn chunked_errors() {
+        let mut array = ChunkedArray::new(&["Storage", "recordings"]);
+        array.feed(&INPUT.as_bytes()[..60]).unwrap();
+        assert!(matches!(array.finish(), Err(ParseError::UnexpectedEnd(_))));
+
+        let mut array = ChunkedArray::new(&["Storage", "recordings"]);
+        let input = "Storage = {\n\t[\"recordings\"] = {\n\t\t{},\n\t\t12,\n\t},\n}\n";
+        match array.feed(input.as_bytes()) {
+            Err(ParseError::SyntaxError(err)) => {
+                assert_eq!(err.offset, input.find("12").unwrap());
+                assert_eq!((err.line, err.column), (4, 3));
+            }
+            result => panic!("expected syntax error, found {:?}", result),
+        }
+
+        for element in ["'a'", "[[a]]"] {
+            let mut array = ChunkedArray::new(&["Storage", "recordings"]);
+            let input = format!(
+                "Storage = {{\n\t[\"recordings\"] = {{\n\t\t{}, -- [1]\n\t}},\n}}\n",
+                element
+            );
+            match array.feed(input.as_bytes()) {
+                Err(ParseError::SyntaxError(err)) => {
+                    assert_eq!(err.offset, input.find(element).unwrap());
+                }
+                result => panic!("expected syntax error, found {:?}", result),
+            }
+        }
+
+        let mut array = ChunkedArray::new(&["Storage", "recordings"]);
+        let input = b"-- caf\xc3\xa9\n-- d\xc3\xa9j\xc3\xa0 vu \xff\nStorage = {\n\t[\"recordings\"] = {\n\t},\n}\n";
+        let result = input
+            .chunks(3)
+            .map(|chunk| array.feed(chunk))
+            .find(Result::is_err);
+        match result {
+            Some(Err(ParseError::SyntaxError(err))) => {
+                assert_eq!(err.offset, 22);
+                assert_eq!((err.line, err.column), (2, 12));
+            }
+            result => panic!("expected syntax error, found {:?}", result),
+        }
+    }
+
+    #[test]
+    fn chunked_search_keeps_little() {
+        let mut array = ChunkedArray::new(&["Storage", "recordings"]);
+        let filler = "Other = { [\"recordings\"] = 1, \"Storage = {\" }\n".repeat(100);
+        for chunk in filler.as_bytes().chunks(7) {
+            array.feed(chunk).unwrap();
+            assert!(array.buffer.len() < 7 + "Storage".len());
+        }
+
+        let input = "Storage = {\n\t[\"other\"] = { [\"recordings\"] = { 1 } },\n\t[\"recordings\"] = {\n\t\t{},\n\t},\n}\n";
+        let elements: Vec<_> = input
+            .as_bytes()
+            .chunks(7)
+            .flat_map(|chunk| array.feed(chunk).unwrap())
+            .collect();
+        array.finish().unwrap();
+        assert_eq!(elements.len(), 1);
+        assert_eq!(
+            elements[0].offset,
+            filler.len() + input.find("{},\n\t},").unwrap()
+        );
+    }
+}
@@ -0,0 +1,588 @@
+//! Editing SavedVariables files in place, leaving everything that isn't edited exactly as it was.
+
+use std::{borrow::Cow, collections::HashSet, ops::Range};
+
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{digit1, not_line_ending, one_of, space0},
+    combinator::{consumed, cut, eof, map, not, opt, peek, recognize},
+    error::context,
+    multi::{many0, separated_list1},
+    sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
+    Offset,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    field_key, field_separator, identifier, long_bracket, scalar, ser::to_string_indented, spacing,
+    table_empty, ws, Deserializer, IResult, Key, Limits, ParseError, ParseOptions, SerializeError,
+    Value, ValueParser,
+};
+
+/// A SavedVariables file parsed only as far as needed to locate every value in it, so that parts of
+/// it can be rewritten with [`Document::edit`].
+///
+/// Unlike [`crate::globals_from_str`], this keeps every assignment and table entry in file order,
+/// including repeated keys, along with its byte range in the file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document<'a> {
+    source: &'a str,
+    /// The limits the file was parsed with, which also apply when deserializing it.
+    limits: Limits,
+    pub globals: Vec<Assignment<'a>>,
+}
+
+/// A top-level `name = value`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment<'a> {
+    pub name: Cow<'a, str>,
+    /// Byte range of the whole assignment in the file.
+    pub span: Range<usize>,
+    pub value: Node<'a>,
+}
+
+/// A value, along with where it is in the file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node<'a> {
+    /// Byte range of the value in the file.
+    pub span: Range<usize>,
+    pub kind: NodeKind<'a>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeKind<'a> {
+    Scalar(Value<'a>),
+    /// The entries of a table constructor, in file order.
+    Table(Vec<Entry<'a>>),
+}
+
+/// One entry of a table constructor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry<'a> {
+    /// The key of a keyed entry. `None` for positional entries.
+    pub key: Option<Key<'a>>,
+    /// The (1-based) position of a positional entry.
+    pub index: Option<usize>,
+    pub value: Node<'a>,
+    /// Byte range of the entry: its key, value and separator, and a line comment after the
+    /// separator on the same line, such as WoW's `-- [N]`.
+    pub span: Range<usize>,
+    /// Byte range of the number in the `-- [N]` comment of a positional entry, if it has one.
+    pub index_comment: Option<Range<usize>>,
+}
+
+impl<'a> Document<'a> {
+    /// Parse a SavedVariables file with the default [`Limits`].
+    pub fn parse(source: &'a str) -> Result<Document<'a>, ParseError> {
+        Document::parse_with(source, Limits::default())
+    }
+
+    /// Parse a SavedVariables file with `limits` instead of the default ones.
+    pub fn parse_with(source: &'a str, limits: Limits) -> Result<Document<'a>, ParseError> {
+        let builder = Builder {
+            source,
+            parser: ValueParser::new(ParseOptions {
+                limits,
+                ..Default::default()
+            }),
+        };
+        let (_, globals) = builder
+            .assignments(source)
+            .map_err(|e| builder.parser.error(source, e))?;
+        Ok(Document {
+            source,
+            limits,
+            globals,
+        })
+    }
+
+    pub fn source(&self) -> &'a str {
+        self.source
+    }
+
+    /// The assignment to the global `name`. If there is more than one, this is the last, as that is
+    /// the one Lua keeps.
+    pub fn global(&self, name: &str) -> Option<&Assignment<'a>> {
+        self.globals.iter().rev().find(|global| global.name == name)
+    }
+
+    /// The source text of `node`.
+    pub fn text(&self, node: &Node) -> &'a str {
+        &self.source[node.span.clone()]
+    }
+
+    /// Deserialize `node`, with errors located in the whole file.
+    pub fn deserialize<T: Deserialize<'a>>(&self, node: &Node) -> Result<T, ParseError> {
+        Deserializer::from_span(self.source, self.text(node))
+            .with_limits(self.limits)
+            .read()
+    }
+
+    /// Start editing the file.
+    pub fn edit(&self) -> Edits<'_, 'a> {
+        Edits {
+            document: self,
+            replacements: vec![],
+            removed: HashSet::new(),
+        }
+    }
+}
+
+impl<'a> Node<'a> {
+    /// The entries of a table, or nothing for any other value.
+    pub fn entries(&self) -> &[Entry<'a>] {
+        match &self.kind {
+            NodeKind::Table(entries) => entries,
+            NodeKind::Scalar(_) => &[],
+        }
+    }
+
+    /// Look up an entry of a table the way Lua would: positional entries first, then the last
+    /// entry with the key.
+    pub fn get<'k>(&self, key: impl Into<Key<'k>>) -> Option<&Node<'a>> {
+        let key = key.into();
+        let entries = self.entries();
+        let positional = match key {
+            Key::Int(ix) => usize::try_from(ix)
+                .ok()
+                .and_then(|ix| entries.iter().find(|entry| entry.index == Some(ix))),
+            _ => None,
+        };
+        positional
+            .or_else(|| {
+                entries
+                    .iter()
+                    .rev()
+                    .find(|entry| entry.key.as_ref() == Some(&key))
+            })
+            .map(|entry| &entry.value)
+    }
+}
+
+/// Builds a [`Document`], counting what it reads against the limits.
+struct Builder<'a> {
+    source: &'a str,
+    parser: ValueParser<'a>,
+}
+
+impl<'a> Builder<'a> {
+    /// The byte range of `text`, which was consumed from the source.
+    fn span(&self, text: &'a str) -> Range<usize> {
+        let start = self.source.offset(text);
+        start..start + text.len()
+    }
+
+    fn assignments(&self, input: &'a str) -> IResult<'a, Vec<Assignment<'a>>> {
+        delimited(
+            spacing,
+            many0(terminated(|i| self.assignment(i), spacing)),
+            context("global assignment", eof),
+        )(input)
+    }
+
+    fn assignment(&self, input: &'a str) -> IResult<'a, Assignment<'a>> {
+        let (rest, (text, (name, value))) = consumed(separated_pair(
+            identifier,
+            ws(tag("=")),
+            cut(|i| self.node(i)),
+        ))(input)?;
+        let span = self.span(text);
+        Ok((rest, Assignment { name, span, value }))
+    }
+
+    fn node(&self, input: &'a str) -> IResult<'a, Node<'a>> {
+        let (rest, (text, kind)) = consumed(context(
+            "value",
+            alt((
+                map(|i| self.table(i), NodeKind::Table),
+                map(scalar, NodeKind::Scalar),
+            )),
+        ))(input)?;
+        if let NodeKind::Scalar(value) = &kind {
+            self.parser.count(input, value)?;
+        }
+        let span = self.span(text);
+        Ok((rest, Node { span, kind }))
+    }
+
+    /// A key and value, as [`ValueParser::field`] reads them.
+    fn field(&self, input: &'a str) -> IResult<'a, (Option<Key<'a>>, Node<'a>)> {
+        alt((
+            pair(map(field_key, Some), cut(|i| self.node(i))),
+            map(|i| self.node(i), |node| (None, node)),
+        ))(input)
+    }
+
+    /// The entries of a table constructor, with the same grammar as [`ValueParser::table`].
+    fn table(&self, input: &'a str) -> IResult<'a, Vec<Entry<'a>>> {
+        peek(tag("{"))(input)?;
+        self.parser.check(input, self.parser.budget.enter())?;
+        self.parser.check(input, self.parser.budget.element())?;
+        let fields = alt((
+            map(table_empty, |_| vec![]),
+            preceded(
+                ws(tag("{")),
+                cut(terminated(
+                    terminated(
+                        separated_list1(field_separator, consumed(|i| self.field(i))),
+                        opt(field_separator),
+                    ),
+                    ws(context("end of table ('}')", tag("}"))),
+                )),
+            ),
+        ))(input);
+        self.parser.budget.leave();
+        let (rest, fields) = fields?;
+
+        let mut entries = Vec::with_capacity(fields.len());
+        let mut positional = 0;
+        for (text, (key, value)) in fields {
+            let index = key.is_none().then(|| positional + 1);
+            positional += index.is_some() as usize;
+
+            // the entry takes its separator, and a comment after that on the same line, with it
+            let span = self.span(text);
+            let after = &self.source[span.end..];
+            let (next, separator) = opt(preceded(spacing, one_of(",;")))(after)?;
+            let (end, comment) = match separator {
+                Some(_) => opt(trailing_comment)(next)?,
+                None => (after, None),
+            };
+            let index_comment = comment
+                .filter(|_| index.is_some())
+                .and_then(|comment| index_number(comment).ok())
+                .map(|(_, digits)| self.span(digits));
+
+            entries.push(Entry {
+                key,
+                index,
+                value,
+                span: span.start..self.source.offset(end),
+                index_comment,
+            });
+        }
+        Ok((rest, entries))
+    }
+}
+
+/// A `--` comment that runs to the end of the line, not including the line break.
+fn trailing_comment(input: &str) -> IResult<&str> {
+    preceded(
+        space0,
+        recognize(tuple((tag("--"), not(long_bracket), not_line_ending))),
+    )(input)
+}
+
+/// The number in a `-- [N]` comment.
+fn index_number(comment: &str) -> IResult<&str> {
+    preceded(
+        pair(tag("--"), space0),
+        delimited(tag("["), digit1, tag("]")),
+    )(comment)
+}
+
+/// Changes to a [`Document`], which are applied all at once by [`Edits::finish`]. Everything they
+/// don't touch is copied over byte for byte.
+///
+/// Each change must be to a part of the file that no other change touches, e.g. a value can't be
+/// replaced after the entry holding it was removed.
+pub struct Edits<'d, 'a> {
+    document: &'d Document<'a>,
+    /// Sorted and never overlapping.
+    replacements: Vec<(Range<usize>, String)>,
+    /// Where each removed entry starts.
+    removed: HashSet<usize>,
+}
+
+impl<'d, 'a> Edits<'d, 'a> {
+    /// Replace `node` with `lua`, which is written as-is.
+    ///
+    /// # Panics
+    ///
+    /// If `node` overlaps a part of the file that was already changed.
+    pub fn replace(&mut self, node: &Node, lua: impl Into<String>) {
+        self.push(node.span.clone(), lua.into());
+    }
+
+    /// Replace `node` with `value`, written as [`crate::to_string`] does, indented to match the
+    /// line it is on.
+    ///
+    /// # Panics
+    ///
+    /// If `node` overlaps a part of the file that was already changed.
+    pub fn set<T: ?Sized + Serialize>(
+        &mut self,
+        node: &Node,
+        value: &T,
+    ) -> Result<(), SerializeError> {
+        let source = self.document.source;
+        let line_start = source[..node.span.start].rfind('\n').map_or(0, |ix| ix + 1);
+        let indent = source[line_start..]
+            .chars()
+            .take_while(|&c| c == '\t')
+            .count();
+        let lua = to_string_indented(value, indent)?;
+        self.replace(node, lua);
+        Ok(())
+    }
+
+    /// Remove `entry` from its table, along with its line if nothing else is on it. The `-- [N]`
+    /// comments of the positional entries after it are renumbered to match their new positions.
+    ///
+    /// # Panics
+    ///
+    /// If `entry` overlaps a part of the file that was already changed.
+    pub fn remove(&mut self, entry: &Entry) {
+        let source = self.document.source;
+        let Range { start, end } = entry.span;
+        let line_start = source[..start].rfind('\n').map_or(0, |ix| ix + 1);
+        let line_end = source[end..]
+            .find('\n')
+            .map_or(source.len(), |ix| end + ix + 1);
+        let blank = |s: &str| s.chars().all(char::is_whitespace);
+
+        let range = if blank(&source[line_start..start]) && blank(&source[end..line_end]) {
+            line_start..line_end
+        } else {
+            let spaces = source[end..].len() - source[end..].trim_start_matches([' ', '\t']).len();
+            start..end + spaces
+        };
+        self.push(range, String::new());
+        self.removed.insert(start);
+    }
+
+    /// Apply the changes, returning the edited file.
+    pub fn finish(mut self) -> String {
+        let document = self.document;
+        for global in &document.globals {
+            self.renumber(&global.value);
+        }
+
+        let source = document.source;
+        let mut output = String::with_capacity(source.len());
+        let mut copied = 0;
+        for (range, text) in &self.replacements {
+            output.push_str(&source[copied..range.start]);
+            output.push_str(text);
+            copied = range.end;
+        }
+        output.push_str(&source[copied..]);
+        output
+    }
+
+    fn push(&mut self, range: Range<usize>, text: String) {
+        let ix = self
+            .replacements
+            .partition_point(|(other, _)| other.start < range.start);
+        let overlaps_previous = ix > 0 && self.replacements[ix - 1].0.end > range.start;
+        let overlaps_next = self
+            .replacements
+            .get(ix)
+            .is_some_and(|(other, _)| other.start < range.end);
+        assert!(
+            !(overlaps_previous || overlaps_next),
+            "edits of a SavedVariables file must not overlap"
+        );
+        self.replacements.insert(ix, (range, text));
+    }
+
+    /// Whether `span` is part of a replaced value or removed entry.
+    fn is_replaced(&self, span: &Range<usize>) -> bool {
+        let ix = self
+            .replacements
+            .partition_point(|(other, _)| other.start <= span.start);
+        ix > 0 && self.replacements[ix - 1].0.end >= span.end
+    }
+
+    /// Renumber the index comments in `node` and any tables in it that come after removed
+    /// positional entries.
+    fn renumber(&mut self, node: &'d Node<'a>) {
+        if self.is_replaced(&node.span) {
+            return;
+        }
+        let mut removed = 0;
+        for entry in node.entries() {
+            if self.removed.contains(&entry.span.start) {
+                removed += entry.index.is_some() as usize;
+                continue;
+            }
+            if let (Some(index), Some(comment), true) =
+                (entry.index, &entry.index_comment, removed > 0)
+            {
+                self.push(comment.clone(), (index - removed).to_string());
+            }
+            self.renumber(&entry.value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::{Document, NodeKind};
+    use crate::{Key, LimitExceeded, Limits, ParseError, Value};
+
+    const INPUT: &str = "\
+-- written by hand
+Storage = {
+\t[\"recordings\"] = {
+\t\t{
+\t\t\t[\"date\"] = 100,
+\t\t\t[\"data\"] = \"old\",
+\t\t}, -- [1]
+\t\t{
+\t\t\t[\"date\"] = 200, -- keep this
+\t\t\t[\"data\"] = { 1.50, 1e2 },
+\t\t}, -- [2]
+\t\t{
+\t\t\t[\"date\"] = 300,
+\t\t\t[\"data\"] = [[raw]],
+\t\t}, -- [3]
+\t},
+\t[\"version\"] = 2;
+}
+Other = { 'a', 'b' ; nil, [5] = true }
+";
+
+    #[test]
+    fn document_spans() {
+        let document = Document::parse(INPUT).unwrap();
+        assert_eq!(document.edit().finish(), INPUT);
+        assert_eq!(
+            document
+                .globals
+                .iter()
+                .map(|global| global.name.as_ref())
+                .collect::<Vec<_>>(),
+            vec!["Storage", "Other"]
+        );
+
+        let storage = &document.global("Storage").unwrap().value;
+        let recordings = storage.get("recordings").unwrap();
+        assert_eq!(recordings.entries().len(), 3);
+        let second = &recordings.entries()[1];
+        assert_eq!(second.index, Some(2));
+        assert_eq!(&INPUT[second.index_comment.clone().unwrap()], "2");
+        assert!(INPUT[second.span.clone()].starts_with("{\n\t\t\t[\"date\"] = 200"));
+        assert!(INPUT[second.span.clone()].ends_with("}, -- [2]"));
+        assert_eq!(
+            document.text(recordings.get(2).unwrap().get("data").unwrap()),
+            "{ 1.50, 1e2 }"
+        );
+        assert_eq!(
+            document
+                .deserialize::<Vec<f64>>(recordings.get(2).unwrap().get("data").unwrap())
+                .unwrap(),
+            vec![1.5, 100.0]
+        );
+        assert_eq!(
+            storage.get("version").unwrap().kind,
+            NodeKind::Scalar(Value::Int(2))
+        );
+
+        let other = &document.global("Other").unwrap().value;
+        assert_eq!(other.entries()[2].index, Some(3));
+        assert_eq!(
+            other.get(5).unwrap().kind,
+            NodeKind::Scalar(Value::Bool(true))
+        );
+        assert_eq!(
+            other.get(Key::Int(2)).map(|node| document.text(node)),
+            Some("'b'")
+        );
+    }
+
+    #[test]
+    fn document_edits() {
+        let document = Document::parse(INPUT).unwrap();
+        let recordings = document
+            .global("Storage")
+            .unwrap()
+            .value
+            .get("recordings")
+            .unwrap();
+        let mut edits = document.edit();
+        for entry in recordings.entries() {
+            let date: i64 = document
+                .deserialize(entry.value.get("date").unwrap())
+                .unwrap();
+            if date < 150 {
+                edits.remove(entry);
+            }
+        }
+        edits
+            .set(recordings.get(3).unwrap().get("data").unwrap(), &[1, 2])
+            .unwrap();
+        let other = &document.global("Other").unwrap().value;
+        edits.remove(&other.entries()[0]);
+        edits.replace(other.get(5).unwrap(), "false");
+
+        assert_eq!(
+            edits.finish(),
+            "\
+-- written by hand
+Storage = {
+\t[\"recordings\"] = {
+\t\t{
+\t\t\t[\"date\"] = 200, -- keep this
+\t\t\t[\"data\"] = { 1.50, 1e2 },
+\t\t}, -- [1]
+\t\t{
+\t\t\t[\"date\"] = 300,
+\t\t\t[\"data\"] = {
+\t\t\t\t1, -- [1]
+\t\t\t\t2, -- [2]
+\t\t\t},
+\t\t}, -- [2]
+\t},
+\t[\"version\"] = 2;
+}
+Other = { 'b' ; nil, [5] = false }
+"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "must not overlap")]
+    fn document_overlapping_edits() {
+        let document = Document::parse(INPUT).unwrap();
+        let recordings = document
+            .global("Storage")
+            .unwrap()
+            .value
+            .get("recordings")
+            .unwrap();
+        let mut edits = document.edit();
+        edits.remove(&recordings.entries()[0]);
+        edits.replace(recordings.get(1).unwrap().get("data").unwrap(), "nil");
+    }
+
+    #[test]
+    fn document_errors() {
+        match Document::parse("Storage = {\n\t[\"a\"] = 1\n\t[\"b\"] = 2,\n}\n") {
+            Err(ParseError::SyntaxError(err)) => assert_eq!((err.line, err.column), (3, 2)),
+            result => panic!("expected a syntax error, got {:?}", result),
+        }
+        let nested = format!("Storage = {}{}", "{".repeat(1000), "}".repeat(1000));
+        assert!(matches!(
+            Document::parse(&nested),
+            Err(ParseError::LimitExceeded(_))
+        ));
+
+        let limits = Limits {
+            max_elements: 3,
+            ..Default::default()
+        };
+        let input = "Storage = { 1, 2 }\nOther = { 1, 2, 3 }\n";
+        assert!(matches!(
+            Document::parse_with(input, limits),
+            Err(ParseError::LimitExceeded(LimitExceeded::Elements(3)))
+        ));
+        let document = Document::parse_with("Storage = { 1, 2 }", limits).unwrap();
+        assert!(document
+            .deserialize::<Vec<i64>>(&document.globals[0].value)
+            .is_ok());
+    }
+}
@@ -0,0 +1,184 @@
+//! Position-aware errors for SavedVariables text and the values parsed from it.
+
+use std::fmt::{Display, Write};
+
+use nom::{
+    error::{VerboseError, VerboseErrorKind},
+    Offset,
+};
+
+use crate::Key;
+
+/// Longest stretch of the offending line shown in a snippet. SavedVariables lines are usually
+/// short, but encoded recordings are a single multi-kilobyte string.
+const SNIPPET_WIDTH: usize = 80;
+
+/// Find the start of the line containing `offset`, along with its 1-based line and column.
+fn locate(input: &str, offset: usize) -> (usize, usize, usize) {
+    let line_start = input[..offset].rfind('\n').map(|ix| ix + 1).unwrap_or(0);
+    let line = input[..line_start].matches('\n').count() + 1;
+    let column = input[line_start..offset].chars().count() + 1;
+    (line_start, line, column)
+}
+
+/// A syntax error at a specific location in the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    /// Byte offset of the error in the input.
+    pub offset: usize,
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column number, in characters.
+    pub column: usize,
+    /// Description of what the parser was looking for.
+    pub expected: String,
+    /// The offending line (or the part of it near the error), with a caret under the error.
+    pub snippet: String,
+}
+
+impl SyntaxError {
+    /// Build an error at `offset` in `input`.
+    pub fn new(input: &str, offset: usize, expected: String) -> SyntaxError {
+        let offset = offset.min(input.len());
+        let (line_start, line, column) = locate(input, offset);
+        let line_end = input[offset..]
+            .find('\n')
+            .map(|ix| ix + offset)
+            .unwrap_or(input.len());
+
+        let text = input[line_start..line_end].trim_end_matches('\r');
+        let skip = (column - 1).saturating_sub(SNIPPET_WIDTH / 2);
+        let mut snippet: String = text.chars().skip(skip).take(SNIPPET_WIDTH).collect();
+        let _ = write!(snippet, "\n{}^", " ".repeat(column - 1 - skip));
+
+        SyntaxError {
+            offset,
+            line,
+            column,
+            expected,
+            snippet,
+        }
+    }
+
+    /// Convert a nom error into a `SyntaxError`. The error is reported at the furthest point the
+    /// parser reached, since that is almost always where the actual problem is.
+    pub(crate) fn from_nom(input: &str, err: nom::Err<VerboseError<&str>>) -> SyntaxError {
+        let errors = match err {
+            nom::Err::Error(e) | nom::Err::Failure(e) => e.errors,
+            nom::Err::Incomplete(_) => {
+                return SyntaxError::new(input, input.len(), "more input".to_string())
+            }
+        };
+
+        let offset = errors
+            .iter()
+            .map(|(rest, _)| input.offset(rest))
+            .max()
+            .unwrap_or(0);
+        let at_offset = || {
+            errors
+                .iter()
+                .filter(move |(rest, _)| input.offset(rest) == offset)
+                .map(|(_, kind)| kind)
+        };
+
+        let expected = at_offset()
+            .find_map(|kind| match kind {
+                VerboseErrorKind::Context(ctx) => Some(ctx.to_string()),
+                _ => None,
+            })
+            .or_else(|| {
+                at_offset().find_map(|kind| match kind {
+                    VerboseErrorKind::Char(c) => Some(format!("'{}'", c)),
+                    _ => None,
+                })
+            })
+            .or_else(|| {
+                at_offset().find_map(|kind| match kind {
+                    VerboseErrorKind::Nom(kind) => Some(kind.description().to_lowercase()),
+                    _ => None,
+                })
+            })
+            .unwrap_or_else(|| "valid input".to_string());
+
+        SyntaxError::new(input, offset, expected)
+    }
+}
+
+impl Display for SyntaxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: expected {}\n{}",
+            self.line, self.column, self.expected, self.snippet
+        )
+    }
+}
+
+impl std::error::Error for SyntaxError {}
+
+/// A problem in the input that did not prevent it from being parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    /// Byte offset of the problem in the input.
+    pub offset: usize,
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column number, in characters.
+    pub column: usize,
+    pub message: String,
+}
+
+impl Warning {
+    pub(crate) fn new(input: &str, offset: usize, message: String) -> Warning {
+        let offset = offset.min(input.len());
+        let (_, line, column) = locate(input, offset);
+        Warning {
+            offset,
+            line,
+            column,
+            message,
+        }
+    }
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+/// The chain of table keys leading to a value, e.g. `recordings[7].data.scripts["Foo"]`.
+/// Positional entries use their (1-based) Lua index.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Path(pub Vec<Key<'static>>);
+
+pub(crate) fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+impl Display for Path {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (ix, key) in self.0.iter().enumerate() {
+            match key {
+                Key::String(s) if is_identifier(s) => {
+                    if ix > 0 {
+                        f.write_char('.')?;
+                    }
+                    f.write_str(s)?;
+                }
+                Key::String(s) => write!(f, "[{:?}]", s)?,
+                Key::Int(i) => write!(f, "[{}]", i)?,
+                Key::Float(v) => write!(f, "[{}]", v)?,
+                Key::Bool(b) => write!(f, "[{}]", b)?,
+            }
+        }
+        Ok(())
+    }
+}
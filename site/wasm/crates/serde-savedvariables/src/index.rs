@@ -0,0 +1,392 @@
+//! Accessors for inspecting a `Value` tree without deserializing it into a type.
+
+use std::{
+    borrow::{Borrow, Cow},
+    hash::{Hash, Hasher},
+    ops,
+};
+
+use crate::{Key, Table, Value};
+
+/// Returned when indexing a missing entry, since Lua reads those as `nil`.
+static NIL: Value<'static> = Value::Nil;
+
+/// A type that can look up an entry of a [`Table`]: integers (1-based, as in Lua), strings and
+/// [`Key`]s.
+///
+/// This trait is sealed, and is only used by [`Table::get`], [`Value::get`] and the `Index` impls.
+pub trait TableIndex: private::Sealed {
+    #[doc(hidden)]
+    fn index_into<'v, 'a>(&self, table: &'v Table<'a>) -> Option<Cow<'v, Value<'a>>>;
+
+    /// The 0-based position this looks up among positional entries, if it is an index.
+    #[doc(hidden)]
+    fn position(&self) -> Option<usize> {
+        None
+    }
+}
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for i64 {}
+    impl Sealed for str {}
+    impl Sealed for String {}
+    impl Sealed for crate::Key<'_> {}
+    impl<T: ?Sized + Sealed> Sealed for &T {}
+}
+
+/// A key of any lifetime, so that a map keyed by `Key<'a>` can be searched with a borrowed key
+/// without copying it into the map's lifetime.
+trait KeyRef {
+    fn key(&self) -> Key<'_>;
+}
+
+impl KeyRef for Key<'_> {
+    fn key(&self) -> Key<'_> {
+        self.reborrow()
+    }
+}
+
+impl<'a: 'k, 'k> Borrow<dyn KeyRef + 'k> for Key<'a> {
+    fn borrow(&self) -> &(dyn KeyRef + 'k) {
+        self
+    }
+}
+
+impl PartialEq for dyn KeyRef + '_ {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for dyn KeyRef + '_ {}
+
+/// The same as `Key`'s, as `Borrow` requires.
+impl Hash for dyn KeyRef + '_ {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+/// Look up a keyed entry.
+fn named<'v, 'a>(table: &'v Table<'a>, key: &Key<'_>) -> Option<Cow<'v, Value<'a>>> {
+    match table {
+        Table::Named(named) | Table::MixedTable { named, .. } => {
+            named.get(key as &dyn KeyRef).map(Cow::Borrowed)
+        }
+        _ => None,
+    }
+}
+
+impl TableIndex for i64 {
+    fn index_into<'v, 'a>(&self, table: &'v Table<'a>) -> Option<Cow<'v, Value<'a>>> {
+        // positional entries win over explicit keys, as in `sequence_of`
+        let positional = match (table, self.position()) {
+            (Table::Array(array) | Table::MixedTable { array, .. }, Some(ix)) => {
+                array.get(ix).map(Cow::Borrowed)
+            }
+            (Table::FloatArray(floats), Some(ix)) => {
+                floats.get(ix).map(|f| Cow::Owned(Value::Float(*f)))
+            }
+            _ => None,
+        };
+        positional.or_else(|| named(table, &Key::Int(*self)))
+    }
+
+    fn position(&self) -> Option<usize> {
+        usize::try_from(*self)
+            .ok()
+            .filter(|ix| *ix >= 1)
+            .map(|ix| ix - 1)
+    }
+}
+
+impl TableIndex for str {
+    fn index_into<'v, 'a>(&self, table: &'v Table<'a>) -> Option<Cow<'v, Value<'a>>> {
+        named(table, &Key::String(Cow::Borrowed(self)))
+    }
+}
+
+impl TableIndex for String {
+    fn index_into<'v, 'a>(&self, table: &'v Table<'a>) -> Option<Cow<'v, Value<'a>>> {
+        self.as_str().index_into(table)
+    }
+}
+
+impl<'k> TableIndex for Key<'k> {
+    fn index_into<'v, 'a>(&self, table: &'v Table<'a>) -> Option<Cow<'v, Value<'a>>> {
+        match self {
+            Key::Int(ix) => ix.index_into(table),
+            key => named(table, key),
+        }
+    }
+
+    fn position(&self) -> Option<usize> {
+        match self {
+            Key::Int(ix) => ix.position(),
+            _ => None,
+        }
+    }
+}
+
+impl<T: ?Sized + TableIndex> TableIndex for &T {
+    fn index_into<'v, 'a>(&self, table: &'v Table<'a>) -> Option<Cow<'v, Value<'a>>> {
+        (**self).index_into(table)
+    }
+
+    fn position(&self) -> Option<usize> {
+        (**self).position()
+    }
+}
+
+impl<'a> Table<'a> {
+    /// Look up an entry by key or by (1-based) index. Entries that were never assigned are `None`.
+    ///
+    /// The numbers in a `FloatArray` aren't stored as `Value`s, so those entries are returned as
+    /// an owned `Value::Float`. All others are borrowed.
+    pub fn get<I: TableIndex>(&self, index: I) -> Option<Cow<'_, Value<'a>>> {
+        index.index_into(self)
+    }
+
+    /// The number of entries in the table, both positional and keyed.
+    pub fn len(&self) -> usize {
+        match self {
+            Table::Empty => 0,
+            Table::Named(named) => named.len(),
+            Table::Array(array) => array.len(),
+            Table::FloatArray(floats) => floats.len(),
+            Table::MixedTable { array, named } => array.len() + named.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over all entries, with positional entries first under their Lua index. Values are
+    /// borrowed or owned as in [`Table::get`].
+    pub fn iter(&self) -> impl Iterator<Item = (Key<'_>, Cow<'_, Value<'a>>)> {
+        let (array, floats, named) = match self {
+            Table::Empty => (&[][..], &[][..], None),
+            Table::Named(named) => (&[][..], &[][..], Some(named)),
+            Table::Array(array) => (array.as_slice(), &[][..], None),
+            Table::FloatArray(floats) => (&[][..], floats.as_slice(), None),
+            Table::MixedTable { array, named } => (array.as_slice(), &[][..], Some(named)),
+        };
+        let positional = array
+            .iter()
+            .map(Cow::Borrowed)
+            .chain(floats.iter().map(|f| Cow::Owned(Value::Float(*f))));
+        positional
+            .enumerate()
+            .map(|(i, v)| (Key::Int(i as i64 + 1), v))
+            .chain(
+                named
+                    .into_iter()
+                    .flatten()
+                    .map(|(k, v)| (k.reborrow(), Cow::Borrowed(v))),
+            )
+    }
+}
+
+impl<'a> Key<'a> {
+    /// A copy of the key that borrows its string rather than cloning it.
+    fn reborrow(&self) -> Key<'_> {
+        match self {
+            Key::String(s) => Key::String(Cow::Borrowed(s)),
+            Key::Bool(b) => Key::Bool(*b),
+            Key::Int(i) => Key::Int(*i),
+            Key::Float(f) => Key::Float(*f),
+        }
+    }
+}
+
+impl<'a> Value<'a> {
+    pub fn is_nil(&self) -> bool {
+        matches!(self, Value::Nil)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Any number as a float. This is how Lua itself sees them, so very large integers may be
+    /// rounded.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Int(i) => Some(*i as f64),
+            Value::BigInt(i) => Some(*i as f64),
+            Value::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_table(&self) -> Option<&Table<'a>> {
+        match self {
+            Value::Table(table) => Some(table),
+            _ => None,
+        }
+    }
+
+    /// Look up an entry of a table by key or by (1-based) index, as in [`Table::get`]. Returns
+    /// `None` if this is not a table or the entry is missing.
+    pub fn get<I: TableIndex>(&self, index: I) -> Option<Cow<'_, Value<'a>>> {
+        self.as_table()?.get(index)
+    }
+
+    /// Follow a path of `.`-separated keys, e.g. `"recordings.3.encounter"`. Segments that are
+    /// integers are treated as indices.
+    pub fn get_path(&self, path: &str) -> Option<Cow<'_, Value<'a>>> {
+        path.split('.').filter(|_| !path.is_empty()).try_fold(
+            Cow::Borrowed(self),
+            |value, segment| {
+                // only tables have entries, and those are always borrowed
+                let Cow::Borrowed(value) = value else {
+                    return None;
+                };
+                match segment.parse::<i64>() {
+                    Ok(ix) => value.get(ix),
+                    Err(_) => value.get(segment),
+                }
+            },
+        )
+    }
+}
+
+impl<'a, I: TableIndex> ops::Index<I> for Table<'a> {
+    type Output = Value<'a>;
+
+    /// Like [`Table::get`], but missing entries are `nil` as they would be in Lua.
+    fn index(&self, index: I) -> &Self::Output {
+        if let Table::FloatArray(floats) = self {
+            // the numbers have no `Value` to borrow until one is made for them
+            return index
+                .position()
+                .and_then(|ix| floats.values().get(ix))
+                .unwrap_or(&NIL);
+        }
+        match self.get(index) {
+            Some(Cow::Borrowed(value)) => value,
+            Some(Cow::Owned(_)) => unreachable!("only a FloatArray has owned entries"),
+            None => &NIL,
+        }
+    }
+}
+
+impl<'a, I: TableIndex> ops::Index<I> for Value<'a> {
+    type Output = Value<'a>;
+
+    /// Like [`Value::get`], but missing entries are `nil` as they would be in Lua.
+    fn index(&self, index: I) -> &Self::Output {
+        match self {
+            Value::Table(table) => &table[index],
+            _ => &NIL,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::borrow::Cow;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::{globals_from_str, Key, Table, Value};
+
+    const INPUT: &str = r#"ProfilingData = {
+        ["recordings"] = {
+            {
+                ["encounter"] = "first",
+            }, -- [1]
+            {
+                ["encounter"] = "second",
+                ["times"] = { 1.5, 2, [4] = 3 },
+                [10] = true,
+            }, -- [2]
+        },
+    }"#;
+
+    #[test]
+    fn value_accessors() {
+        let globals = globals_from_str(INPUT).unwrap();
+        let data = &globals["ProfilingData"];
+
+        assert_eq!(
+            data.get_path("recordings.2.encounter")
+                .as_deref()
+                .and_then(Value::as_str),
+            Some("second")
+        );
+        assert_eq!(data["recordings"][1]["encounter"].as_str(), Some("first"));
+        assert_eq!(data["recordings"][2]["times"][1].as_f64(), Some(1.5));
+        assert_eq!(data["recordings"][2]["times"][4].as_i64(), Some(3));
+        assert_eq!(data["recordings"][2][10].as_bool(), Some(true));
+        assert_eq!(data.get_path("recordings.3.encounter"), None);
+        assert!(data["recordings"][3]["encounter"].is_nil());
+        assert!(data["recordings"]["encounter"].is_nil());
+        assert_eq!(data.get_path(""), Some(Cow::Borrowed(data)));
+
+        let times = data["recordings"][2]["times"].as_table().unwrap();
+        assert_eq!(times.len(), 3);
+        let mut entries = times.iter().collect::<Vec<_>>();
+        entries.sort_by_key(|(key, _)| match key {
+            Key::Int(i) => *i,
+            _ => unreachable!(),
+        });
+        assert_eq!(
+            entries,
+            vec![
+                (Key::Int(1), Cow::Borrowed(&Value::Float(1.5))),
+                (Key::Int(2), Cow::Borrowed(&Value::Int(2))),
+                (Key::Int(4), Cow::Borrowed(&Value::Int(3))),
+            ]
+        );
+
+        let recording = data["recordings"][2].as_table().unwrap();
+        let key = Key::String(Cow::Owned("encounter".into()));
+        assert_eq!(recording.get(&key), recording.get("encounter"));
+        assert_eq!(recording.get(String::from("times")), recording.get("times"));
+    }
+
+    #[test]
+    fn float_array_accessors() {
+        let floats = Value::Table(Table::FloatArray(vec![0.5, 1.5].into()));
+
+        assert_eq!(floats.get(2).and_then(|v| v.as_f64()), Some(1.5));
+        assert_eq!(floats.get(3), None);
+        assert_eq!(floats.get_path("1"), Some(Cow::Owned(Value::Float(0.5))));
+        assert_eq!(floats.get_path("1.2"), None);
+
+        let table = floats.as_table().unwrap();
+        assert_eq!(table.len(), 2);
+        assert_eq!(
+            table.iter().collect::<Vec<_>>(),
+            vec![
+                (Key::Int(1), Cow::Owned(Value::Float(0.5))),
+                (Key::Int(2), Cow::Owned(Value::Float(1.5))),
+            ]
+        );
+
+        assert_eq!(floats[1], Value::Float(0.5));
+        assert_eq!(floats[Key::Int(2)].as_f64(), Some(1.5));
+        assert!(floats[3].is_nil());
+        assert!(floats["x"].is_nil());
+    }
+}
@@ -1,24 +1,50 @@
-use std::{borrow::Cow, collections::HashMap};
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    hash::{Hash, Hasher},
+    ops::{Deref, DerefMut},
+    sync::OnceLock,
+};
 
+pub use chunked::{ChunkedArray, Element};
+pub use cst::{Assignment, Document, Edits, Entry, Node, NodeKind};
+pub use error::{Path, SyntaxError, Warning};
+pub use index::TableIndex;
+use indexmap::IndexMap;
+pub use limits::{Budget, LimitExceeded, Limits};
 use nom::{
     branch::alt,
-    bytes::complete::{escaped, tag, take_while1},
-    character::complete::{i64 as parse_i64, line_ending, multispace1, none_of, one_of},
-    combinator::{complete, eof, map, not, opt, recognize},
-    error::VerboseError,
-    multi::{fold_many0, many0, separated_list1},
+    bytes::complete::{escaped, is_not, tag, take_until, take_while1},
+    character::complete::{
+        anychar, char, digit1, line_ending, multispace1, none_of, one_of, satisfy, space0,
+    },
+    combinator::{cut, eof, map, map_res, not, opt, peek, recognize, rest as nom_rest},
+    error::{context, ErrorKind, ParseError as _, VerboseError, VerboseErrorKind},
+    multi::{fold_many0, many0, many0_count, separated_list1},
     number::complete::double,
-    sequence::{delimited, separated_pair, terminated},
+    sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
+    Offset,
 };
-
+pub use raw::RawLua;
+use raw::RAW_LUA_TOKEN;
+pub use recover::{recover_array_from_str, Recovered, Skipped};
+pub use ser::{global_to_string, to_string, SerializeError};
 use serde::{
-    de::{
-        self,
-        value::{MapDeserializer, SeqDeserializer},
-        IntoDeserializer, Visitor,
-    },
+    de::{self, DeserializeOwned, IntoDeserializer, Visitor},
     forward_to_deserialize_any, Deserialize,
 };
+pub use stream::Deserializer;
+
+mod chunked;
+mod cst;
+mod error;
+mod index;
+mod limits;
+mod raw;
+mod recover;
+mod ser;
+mod stream;
+mod value_serde;
 
 type IResult<'a, O> = nom::IResult<&'a str, O, VerboseError<&'a str>>;
 
@@ -28,9 +54,15 @@ pub enum Value<'a> {
     Nil,
     Bool(bool),
     Int(i64),
+    /// An integer too large for `i64`. Lua would round it to a double, but they are kept exact
+    /// here so that IDs and timestamps survive.
+    BigInt(i128),
     Float(f64),
     String(Cow<'a, str>),
     Table(Table<'a>),
+    /// Every value given for a key that a table has more than once, in file order. Only made for
+    /// [`DuplicateKeys::CollectAll`], and read and serialized as a sequence.
+    Duplicates(Vec<Value<'a>>),
 }
 
 fn nil(input: &str) -> IResult<Value> {
@@ -45,41 +77,175 @@ fn boolean(input: &str) -> IResult<Value> {
 }
 
 fn int(input: &str) -> IResult<Value> {
-    map(terminated(parse_i64, not(tag("."))), Value::Int)(input)
+    map_res(
+        terminated(recognize(pair(opt(char('-')), digit1)), not(one_of(".eE"))),
+        |digits: &str| match digits.parse::<i64>() {
+            Ok(i) => Ok(Value::Int(i)),
+            Err(_) => digits.parse::<i128>().map(Value::BigInt),
+        },
+    )(input)
+}
+
+/// The ways that infinity and NaN end up written in SavedVariables files. WoW uses the MSVC
+/// spellings (`1.#INF`, `-1.#IND`), but files written by other tools may use the others.
+fn non_finite(input: &str) -> IResult<f64> {
+    let (rest, negative) = map(opt(char('-')), |sign| sign.is_some())(input)?;
+    let (rest, value) = terminated(
+        alt((
+            map(alt((tag("1.#INF"), tag("math.huge"), tag("inf"))), |_| {
+                f64::INFINITY
+            }),
+            map(alt((tag("1.#IND"), tag("1.#QNAN"), tag("nan"))), |_| {
+                f64::NAN
+            }),
+        )),
+        not(satisfy(|c| c.is_alphanumeric() || c == '_')),
+    )(rest)?;
+
+    Ok((rest, if negative { -value } else { value }))
 }
 
 fn float(input: &str) -> IResult<Value> {
-    map(double, Value::Float)(input)
+    map(alt((non_finite, double)), Value::Float)(input)
+}
+
+fn is_hex_byte(digits: Option<&[u8]>) -> bool {
+    matches!(digits, Some(digits) if digits.iter().all(u8::is_ascii_hexdigit))
+}
+
+/// Decode the escape sequences that Lua 5.1 allows in quoted strings. `\xXX` (from Lua 5.2) is
+/// also accepted, since some addons write it.
+///
+/// Borrows the input if there is nothing to decode. Escaped bytes that aren't valid UTF-8 are
+/// replaced with U+FFFD.
+fn unescape(raw: &str) -> Result<Cow<str>, &'static str> {
+    if !raw.contains('\\') {
+        return Ok(Cow::Borrowed(raw));
+    }
+
+    let bytes = raw.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut ix = 0;
+    while ix < bytes.len() {
+        let b = bytes[ix];
+        ix += 1;
+        if b != b'\\' {
+            result.push(b);
+            continue;
+        }
+
+        let c = *bytes.get(ix).ok_or("unterminated escape sequence")?;
+        ix += 1;
+        match c {
+            b'a' => result.push(0x07),
+            b'b' => result.push(0x08),
+            b'f' => result.push(0x0c),
+            b'n' => result.push(b'\n'),
+            b'r' => result.push(b'\r'),
+            b't' => result.push(b'\t'),
+            b'v' => result.push(0x0b),
+            // an escaped line break is a line break. \r\n and \n\r count as a single one.
+            b'\n' | b'\r' => {
+                match bytes.get(ix) {
+                    Some(&next @ (b'\n' | b'\r')) if next != c => ix += 1,
+                    _ => {}
+                }
+                result.push(b'\n');
+            }
+            b'x' if is_hex_byte(bytes.get(ix..ix + 2)) => {
+                let hex = std::str::from_utf8(&bytes[ix..ix + 2]).unwrap();
+                result.push(u8::from_str_radix(hex, 16).unwrap());
+                ix += 2;
+            }
+            b'0'..=b'9' => {
+                // up to 3 decimal digits
+                let mut code = u32::from(c - b'0');
+                for _ in 0..2 {
+                    match bytes.get(ix) {
+                        Some(d) if d.is_ascii_digit() => {
+                            code = code * 10 + u32::from(d - b'0');
+                            ix += 1;
+                        }
+                        _ => break,
+                    }
+                }
+                result.push(u8::try_from(code).map_err(|_| "decimal escape too large")?);
+            }
+            // Lua 5.1 passes any other escaped character through as-is
+            c => result.push(c),
+        }
+    }
+
+    // escapes can produce any byte, but one bad string shouldn't fail the whole file
+    Ok(Cow::Owned(String::from_utf8(result).unwrap_or_else(
+        |err| String::from_utf8_lossy(err.as_bytes()).into_owned(),
+    )))
+}
+
+/// Parse a string delimited by `quote`, decoding any escape sequences in it.
+fn quoted_string<'a>(quote: &'static str) -> impl FnMut(&'a str) -> IResult<'a, Value<'a>> {
+    let normal = if quote == "'" { "\\'" } else { "\\\"" };
+    move |input| {
+        map(
+            preceded(
+                tag(quote),
+                cut(context(
+                    "valid escape sequences",
+                    map_res(
+                        terminated(
+                            escaped(is_not(normal), '\\', anychar),
+                            context("closing quote", tag(quote)),
+                        ),
+                        unescape,
+                    ),
+                )),
+            ),
+            Value::String,
+        )(input)
+    }
 }
 
 fn string_double(input: &str) -> IResult<Value> {
-    map(
-        delimited(
-            one_of("\""),
-            escaped(none_of(r#"""#), '\\', one_of(r#"""#)),
-            one_of("\""),
-        ),
-        |s| Value::String(Cow::Borrowed(s)),
-    )(input)
+    quoted_string("\"")(input)
 }
 
 fn string_single(input: &str) -> IResult<Value> {
-    map(
-        delimited(
-            one_of("'"),
-            escaped(none_of(r#"'"#), '\\', one_of(r#"'"#)),
-            one_of("'"),
-        ),
-        |s| Value::String(Cow::Borrowed(s)),
-    )(input)
+    quoted_string("'")(input)
+}
+
+/// Parse a long bracket of any level (`[[...]]`, `[==[...]==]`, ...) and return its contents.
+/// As in Lua, a line break immediately after the opening bracket is not part of the contents.
+fn long_bracket(input: &str) -> IResult<&str> {
+    let (rest, level) = delimited(tag("["), many0_count(char('=')), tag("["))(input)?;
+    let close = format!("]{}]", "=".repeat(level));
+    let (rest, contents) = cut(context(
+        "closing long bracket",
+        terminated(take_until(close.as_str()), tag(close.as_str())),
+    ))(rest)?;
+
+    let contents = ["\r\n", "\n\r", "\n", "\r"]
+        .into_iter()
+        .find_map(|newline| contents.strip_prefix(newline))
+        .unwrap_or(contents);
+
+    Ok((rest, contents))
+}
+
+fn string_long(input: &str) -> IResult<Value> {
+    map(long_bracket, |s| Value::String(Cow::Borrowed(s)))(input)
+}
+
+fn string(input: &str) -> IResult<Value> {
+    alt((string_double, string_single, string_long))(input)
 }
 
+fn line_comment(input: &str) -> IResult<&str> {
+    terminated(recognize(many0(none_of("\r\n"))), alt((eof, line_ending)))(input)
+}
+
+/// Either a `--` line comment or a `--[[ ]]` block comment (with any level of long bracket).
 fn comment(input: &str) -> IResult<&str> {
-    delimited(
-        tag("--"),
-        recognize(many0(none_of("\r\n"))),
-        alt((eof, line_ending)),
-    )(input)
+    preceded(tag("--"), alt((long_bracket, line_comment)))(input)
 }
 
 /// Lax identifier parser. Allows technically banned identifiers like `2ident`, but we don't
@@ -91,19 +257,99 @@ fn identifier(input: &str) -> IResult<Cow<str>> {
     )(input)
 }
 
-/// Represents a Lua table. We don't support mixing named keys and implicit keys.
+/// The map used for the keyed entries of a table. With the `preserve_order` feature, entries are
+/// kept in the order they were read in. This only affects tables: [`Globals`] are always kept in
+/// file order.
+#[cfg(feature = "preserve_order")]
+pub type Map<K, V> = IndexMap<K, V>;
+#[cfg(not(feature = "preserve_order"))]
+pub type Map<K, V> = std::collections::HashMap<K, V>;
+
+/// Represents a Lua table. The positional part of a `MixedTable` starts at index 1, as in Lua.
 #[derive(Debug, PartialEq, Clone)]
 pub enum Table<'a> {
     Empty,
-    Named(HashMap<Cow<'a, str>, Value<'a>>),
+    Named(Map<Key<'a>, Value<'a>>),
     Array(Vec<Value<'a>>),
-    FloatArray(Vec<f64>),
+    FloatArray(Floats),
     MixedTable {
         array: Vec<Value<'a>>,
-        named: HashMap<Cow<'a, str>, Value<'a>>,
+        named: Map<Key<'a>, Value<'a>>,
     },
 }
 
+/// The numbers of a [`Table::FloatArray`], which derefs to the `Vec<f64>` holding them. They are
+/// only turned into `Value`s when they are borrowed as such, through the `Index` impls.
+#[derive(Default)]
+pub struct Floats {
+    floats: Vec<f64>,
+    values: OnceLock<Vec<Value<'static>>>,
+}
+
+impl Floats {
+    pub fn into_vec(self) -> Vec<f64> {
+        self.floats
+    }
+
+    /// Each number as a `Value::Float`, made the first time they are asked for.
+    fn values(&self) -> &[Value<'static>] {
+        self.values
+            .get_or_init(|| self.floats.iter().copied().map(Value::Float).collect())
+    }
+}
+
+impl From<Vec<f64>> for Floats {
+    fn from(floats: Vec<f64>) -> Self {
+        Floats {
+            floats,
+            values: OnceLock::new(),
+        }
+    }
+}
+
+impl Deref for Floats {
+    type Target = Vec<f64>;
+
+    fn deref(&self) -> &Vec<f64> {
+        &self.floats
+    }
+}
+
+impl DerefMut for Floats {
+    fn deref_mut(&mut self) -> &mut Vec<f64> {
+        // the numbers may change, so the values made from them have to be made again
+        self.values.take();
+        &mut self.floats
+    }
+}
+
+impl IntoIterator for Floats {
+    type Item = f64;
+    type IntoIter = std::vec::IntoIter<f64>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.floats.into_iter()
+    }
+}
+
+impl Clone for Floats {
+    fn clone(&self) -> Self {
+        self.floats.clone().into()
+    }
+}
+
+impl PartialEq for Floats {
+    fn eq(&self, other: &Self) -> bool {
+        self.floats == other.floats
+    }
+}
+
+impl std::fmt::Debug for Floats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.floats.fmt(f)
+    }
+}
+
 fn table_empty(input: &str) -> IResult<Table> {
     map(delimited(tag("{"), spacing, tag("}")), |_| Table::Empty)(input)
 }
@@ -122,77 +368,739 @@ where
     delimited(spacing, inner, spacing)
 }
 
-fn table_string_key(input: &str) -> IResult<Cow<str>> {
-    map(
-        delimited(tag("["), alt((string_single, string_double)), tag("]")),
-        |v| match v {
-            Value::String(s) => s,
-            _ => unreachable!("non-string table key found from string parser?!"),
-        },
-    )(input)
+/// A table key. Lua allows any value other than `nil` as a key, but only these can be written
+/// literally in a SavedVariables file.
+///
+/// Lua 5.1 numbers are all doubles, so integral floats are normalized to `Int` to keep `[1]` and
+/// `[1.0]` the same key.
+#[derive(Debug, Clone)]
+pub enum Key<'a> {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(Cow<'a, str>),
 }
 
-fn named_pair(input: &str) -> IResult<(Cow<str>, Value)> {
-    separated_pair(alt((table_string_key, identifier)), ws(tag("=")), value)(input)
+impl<'a> Key<'a> {
+    /// Detach the key from the input it was parsed from.
+    pub fn into_owned(self) -> Key<'static> {
+        match self {
+            Key::Bool(b) => Key::Bool(b),
+            Key::Int(i) => Key::Int(i),
+            Key::Float(f) => Key::Float(f),
+            Key::String(s) => Key::String(Cow::Owned(s.into_owned())),
+        }
+    }
 }
 
-fn table_named(input: &str) -> IResult<Table> {
-    map(
-        delimited(
-            ws(tag("{")),
-            terminated(separated_list1(ws(tag(",")), named_pair), opt(ws(tag(",")))),
-            ws(tag("}")),
-        ),
-        |entries| {
-            let map = entries.into_iter().collect::<HashMap<_, _>>();
-            Table::Named(map)
-        },
+impl<'a> PartialEq for Key<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Key::Bool(a), Key::Bool(b)) => a == b,
+            (Key::Int(a), Key::Int(b)) => a == b,
+            (Key::Float(a), Key::Float(b)) => a.to_bits() == b.to_bits(),
+            (Key::String(a), Key::String(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl<'a> Eq for Key<'a> {}
+
+impl<'a> Hash for Key<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Key::Bool(b) => b.hash(state),
+            Key::Int(i) => i.hash(state),
+            Key::Float(f) => f.to_bits().hash(state),
+            Key::String(s) => s.hash(state),
+        }
+    }
+}
+
+impl<'a> TryFrom<Value<'a>> for Key<'a> {
+    type Error = Value<'a>;
+
+    /// Convert a value to a key, handing the value back if it cannot be used as one.
+    fn try_from(value: Value<'a>) -> Result<Self, Self::Error> {
+        match value {
+            Value::Bool(b) => Ok(Key::Bool(b)),
+            Value::Int(i) => Ok(Key::Int(i)),
+            // Lua can only use this as a double
+            Value::BigInt(i) => Ok(Key::Float(i as f64)),
+            Value::Float(f) if f.fract() == 0.0 && f >= i64::MIN as f64 && f < i64::MAX as f64 => {
+                Ok(Key::Int(f as i64))
+            }
+            Value::Float(f) if !f.is_nan() => Ok(Key::Float(f)),
+            Value::String(s) => Ok(Key::String(s)),
+            value => Err(value),
+        }
+    }
+}
+
+impl From<bool> for Key<'_> {
+    fn from(b: bool) -> Self {
+        Key::Bool(b)
+    }
+}
+
+impl From<i64> for Key<'_> {
+    fn from(i: i64) -> Self {
+        Key::Int(i)
+    }
+}
+
+impl<'a> From<&'a str> for Key<'a> {
+    fn from(s: &'a str) -> Self {
+        Key::String(Cow::Borrowed(s))
+    }
+}
+
+/// `[<key>]`, where the key is any literal allowed by [`Key`].
+fn table_key(input: &str) -> IResult<Key> {
+    map_res(
+        delimited(tag("["), ws(alt((boolean, int, float, string))), tag("]")),
+        Key::try_from,
     )(input)
 }
 
-fn table_array(input: &str) -> IResult<Table> {
-    map(
-        delimited(
-            ws(tag("{")),
-            terminated(separated_list1(ws(tag(",")), value), opt(ws(tag(",")))),
-            ws(tag("}")),
-        ),
-        Table::Array,
+/// The key of a keyed field, up to and including the `=`.
+fn field_key(input: &str) -> IResult<Key> {
+    terminated(alt((table_key, map(identifier, Key::String))), ws(tag("=")))(input)
+}
+
+/// A single entry in a table constructor.
+enum Field<'a> {
+    Positional(Value<'a>),
+    Keyed(Key<'a>, Value<'a>),
+}
+
+fn field_separator(input: &str) -> IResult<char> {
+    ws(one_of(",;"))(input)
+}
+
+/// A key that appeared more than once in the same table constructor. The positions are the
+/// remaining input at each occurrence.
+struct Duplicate<'a> {
+    key: Key<'a>,
+    first: &'a str,
+    duplicate: &'a str,
+}
+
+impl<'a> Duplicate<'a> {
+    fn warning(&self, input: &str) -> Warning {
+        let first = Warning::new(input, input.offset(self.first), String::new());
+        Warning::new(
+            input,
+            input.offset(self.duplicate),
+            format!(
+                "duplicate key {} (first seen at line {}, column {})",
+                Path(vec![self.key.clone().into_owned()]),
+                first.line,
+                first.column
+            ),
+        )
+    }
+}
+
+/// An array element whose `-- [N]` comment doesn't match its position. The position is the
+/// remaining input after the element.
+struct IndexMismatch<'a> {
+    index: usize,
+    annotated: Option<i64>,
+    position: &'a str,
+}
+
+/// The `-- [N]` comment that WoW writes after each array element (and its separator), on the same
+/// line.
+fn index_comment(input: &str) -> IResult<i64> {
+    preceded(
+        tuple((space0, opt(one_of(",;")), space0, tag("--"), space0)),
+        map_res(delimited(tag("["), digit1, tag("]")), str::parse),
     )(input)
 }
 
-fn table(input: &str) -> IResult<Value> {
-    map(alt((table_empty, table_array, table_named)), Value::Table)(input)
+/// Builds `Value`s according to a set of `ParseOptions`, noting any duplicate keys (and, if asked
+/// to, mismatched index comments) it finds.
+#[derive(Default)]
+struct ValueParser<'a> {
+    options: ParseOptions,
+    budget: Budget,
+    /// The limit that stopped parsing, if any. nom errors can't carry it, so it is kept here.
+    exceeded: Cell<Option<LimitExceeded>>,
+    duplicates: RefCell<Vec<Duplicate<'a>>>,
+    index_mismatches: RefCell<Vec<IndexMismatch<'a>>>,
 }
 
+impl<'a> ValueParser<'a> {
+    fn new(options: ParseOptions) -> Self {
+        ValueParser {
+            options,
+            budget: Budget::new(options.limits),
+            ..Default::default()
+        }
+    }
+
+    /// Stop parsing at `input` if `result` went over a limit.
+    fn check(
+        &self,
+        input: &'a str,
+        result: Result<(), LimitExceeded>,
+    ) -> Result<(), nom::Err<VerboseError<&'a str>>> {
+        result.map_err(|exceeded| {
+            self.exceeded.set(Some(exceeded));
+            nom::Err::Failure(VerboseError::from_error_kind(input, ErrorKind::TooLarge))
+        })
+    }
+
+    /// Count a value that was read, whether or not it is built.
+    fn count(&self, input: &'a str, value: &Value) -> Result<(), nom::Err<VerboseError<&'a str>>> {
+        self.check(
+            input,
+            match value {
+                Value::String(s) => self.budget.string(s.len(), matches!(s, Cow::Owned(_))),
+                _ => self.budget.element(),
+            },
+        )
+    }
+
+    /// Consume a value without building it, stopping at tables nested deeper than the limit allows.
+    fn skip_value(&self, input: &'a str) -> IResult<'a, ()> {
+        self.walk_value(input, |_| ())
+    }
+
+    /// Like [`ValueParser::skip_value`], passing `walk` the tables in the value as they start and
+    /// end, and the keys of their entries.
+    pub(crate) fn walk_value(
+        &self,
+        input: &'a str,
+        mut walk: impl FnMut(Walk<'a>),
+    ) -> IResult<'a, ()> {
+        let skip_table = |i| walk_table_within(i, self.budget.depth_left(), &mut walk);
+        let result = context("value", alt((map(scalar, |_| ()), skip_table)))(input);
+        if let Err(nom::Err::Failure(e)) = &result {
+            if let Some((_, VerboseErrorKind::Nom(ErrorKind::TooLarge))) = e.errors.first() {
+                self.exceeded
+                    .set(Some(LimitExceeded::Depth(self.options.limits.max_depth)));
+            }
+        }
+        result
+    }
+
+    /// Convert a parse error into a `ParseError` for `input`.
+    fn error(&self, input: &str, err: nom::Err<VerboseError<&str>>) -> ParseError {
+        match self.exceeded.get() {
+            Some(exceeded) => exceeded.into(),
+            None => SyntaxError::from_nom(input, err).into(),
+        }
+    }
+
+    fn named_pair(&self, input: &'a str) -> IResult<'a, (Key<'a>, Value<'a>)> {
+        pair(field_key, cut(|i| self.value(i)))(input)
+    }
+
+    fn field(&self, input: &'a str) -> IResult<'a, Field<'a>> {
+        alt((
+            map(|i| self.named_pair(i), |(k, v)| Field::Keyed(k, v)),
+            map(|i| self.value(i), Field::Positional),
+        ))(input)
+    }
+
+    /// A table constructor, with any mix of positional and keyed fields. Only tables that actually
+    /// mix the two become `MixedTable`.
+    fn table_fields(&self, input: &'a str) -> IResult<'a, Table<'a>> {
+        preceded(ws(tag("{")), cut(|i| self.table_entries(i, 0)))(input)
+    }
+
+    /// The entries of a table constructor up to and including its `}`, for a table whose first
+    /// `skipped` positional entries were already read. The positional entries of the result start
+    /// after those.
+    fn table_entries(&self, input: &'a str, skipped: usize) -> IResult<'a, Table<'a>> {
+        let (rest, fields) = terminated(
+            terminated(
+                separated_list1(
+                    field_separator,
+                    tuple((peek(nom_rest), |i| self.field(i), peek(nom_rest))),
+                ),
+                opt(field_separator),
+            ),
+            ws(context("end of table ('}')", tag("}"))),
+        )(input)?;
+
+        let mut array = vec![];
+        let mut named = Map::new();
+        // where each key was first seen
+        let mut seen: Map<Key, &str> = Map::new();
+        // each positional field's index comment, and the input after it
+        let mut annotations = vec![];
+        for (position, field, end) in fields {
+            let (k, v) = match field {
+                Field::Positional(v) => {
+                    array.push(v);
+                    if self.options.check_index_comments {
+                        annotations.push((index_comment(end).ok().map(|(_, ix)| ix), end));
+                    }
+                    continue;
+                }
+                Field::Keyed(k, v) => (k, v),
+            };
+
+            let Some(&first) = seen.get(&k) else {
+                seen.insert(k.clone(), position);
+                named.insert(k, v);
+                continue;
+            };
+            self.duplicates.borrow_mut().push(Duplicate {
+                key: k.clone(),
+                first,
+                duplicate: position,
+            });
+
+            match self.options.duplicate_keys {
+                DuplicateKeys::KeepFirst => {}
+                DuplicateKeys::Error | DuplicateKeys::KeepLast => {
+                    named.insert(k, v);
+                }
+                DuplicateKeys::CollectAll => {
+                    let existing = named.get_mut(&k).expect("seen keys to be present");
+                    match existing {
+                        Value::Duplicates(values) => values.push(v),
+                        _ => {
+                            let first_value = std::mem::replace(existing, Value::Nil);
+                            *existing = Value::Duplicates(vec![first_value, v]);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.note_index_comments(skipped, annotations);
+
+        let table = match (array.is_empty(), named.is_empty()) {
+            (_, true) => Table::Array(array),
+            (true, false) => Table::Named(named),
+            (false, false) => Table::MixedTable { array, named },
+        };
+        Ok((rest, table))
+    }
+
+    /// Check the index comments of a table's positional entries, given with the input after each
+    /// entry, for a table whose first `skipped` positional entries were checked already.
+    fn note_index_comments(&self, skipped: usize, annotations: Vec<(Option<i64>, &'a str)>) {
+        // tables written by hand often have no annotations at all, which is fine
+        if annotations.iter().all(|(annotated, _)| annotated.is_none()) {
+            return;
+        }
+        let mut mismatches = self.index_mismatches.borrow_mut();
+        for (ix, (annotated, position)) in annotations.into_iter().enumerate() {
+            let index = skipped + ix + 1;
+            if annotated != Some(index as i64) {
+                mismatches.push(IndexMismatch {
+                    index,
+                    annotated,
+                    position,
+                });
+            }
+        }
+    }
+
+    fn table(&self, input: &'a str) -> IResult<'a, Value<'a>> {
+        peek(ws(tag("{")))(input)?;
+        self.check(input, self.budget.enter())?;
+        let table = map(alt((table_empty, |i| self.table_fields(i))), Value::Table)(input);
+        self.budget.leave();
+        table
+    }
+
+    fn value(&self, input: &'a str) -> IResult<'a, Value<'a>> {
+        let (rest, value) = context(
+            "value",
+            alt((nil, boolean, int, float, string, |i| self.table(i))),
+        )(input)?;
+        self.count(input, &value)?;
+        Ok((rest, value))
+    }
+
+    fn assignment(&self, input: &'a str) -> IResult<'a, (Cow<'a, str>, Value<'a>)> {
+        separated_pair(identifier, ws(tag("=")), cut(|i| self.value(i)))(input)
+    }
+
+    /// SavedVariables files are a series of `<variable> = <value>` assignments, one per global.
+    fn assignments(&self, input: &'a str) -> IResult<'a, Vec<(Cow<'a, str>, Value<'a>)>> {
+        delimited(
+            spacing,
+            many0(terminated(|i| self.assignment(i), spacing)),
+            context("global assignment", eof),
+        )(input)
+    }
+
+    /// Turn the duplicates found so far into warnings about `input`.
+    fn duplicate_warnings(&self, input: &str) -> Vec<Warning> {
+        self.duplicates
+            .borrow()
+            .iter()
+            .map(|dup| dup.warning(input))
+            .collect()
+    }
+
+    /// Turn the mismatched index comments found so far into warnings about `input`.
+    fn index_warnings(&self, input: &str) -> Vec<Warning> {
+        self.index_mismatches
+            .borrow()
+            .iter()
+            .map(|mismatch| {
+                let message = match mismatch.annotated {
+                    Some(annotated) => format!(
+                        "array element {} is annotated as [{}]",
+                        mismatch.index, annotated
+                    ),
+                    None => format!("array element {} has no index comment", mismatch.index),
+                };
+                Warning::new(input, input.offset(mismatch.position), message)
+            })
+            .collect()
+    }
+
+    /// Every problem found so far in `input`, in file order, or the first duplicate key if those
+    /// are errors.
+    fn warnings(&self, input: &str) -> Result<Vec<Warning>, ParseError> {
+        let mut warnings = self.duplicate_warnings(input);
+        if self.options.duplicate_keys == DuplicateKeys::Error {
+            if let Some(warning) = warnings.first() {
+                return Err(ParseError::DuplicateKey(warning.clone()));
+            }
+        }
+        warnings.extend(self.index_warnings(input));
+        warnings.sort_by_key(|warning| warning.offset);
+        Ok(warnings)
+    }
+}
+
+/// Parse a value with the default options.
+#[cfg(test)]
 fn value(input: &str) -> IResult<Value> {
-    alt((
-        nil,
-        boolean,
-        int,
-        float,
-        string_double,
-        string_single,
-        table,
-    ))(input)
+    ValueParser::default().value(input)
+}
+
+fn scalar(input: &str) -> IResult<Value> {
+    alt((nil, boolean, int, float, string))(input)
+}
+
+/// What [`walk_table_within`] comes across in a table.
+pub(crate) enum Walk<'a> {
+    /// A table with at least one entry starts, at this `{`.
+    Open(&'a str),
+    /// The innermost open table has an entry with this key.
+    Key(Key<'a>),
+    /// The innermost open table ends.
+    Close,
+}
+
+/// Consume a table without building it, failing with `ErrorKind::TooLarge` at any table nested
+/// more than `max_depth` deep, counting this one. Along the way, `walk` is told about every table
+/// that isn't empty and about their keyed entries.
+///
+/// Nested tables are tracked with a counter rather than by recursing, so that this can check input
+/// of any depth before it is deserialized.
+fn walk_table_within<'a>(
+    input: &'a str,
+    max_depth: usize,
+    mut walk: impl FnMut(Walk<'a>),
+) -> IResult<'a, ()> {
+    let too_deep = |at| nom::Err::Failure(VerboseError::from_error_kind(at, ErrorKind::TooLarge));
+    if max_depth == 0 {
+        return Err(too_deep(input));
+    }
+    if let Ok((rest, _)) = table_empty(input) {
+        return Ok((rest, ()));
+    }
+
+    let (mut rest, brace) = ws(tag("{"))(input)?;
+    walk(Walk::Open(brace));
+    let mut depth = 1;
+    loop {
+        // at the start of a field of the innermost open table
+        let (next, key) = opt(field_key)(rest)?;
+        if let Some(key) = key {
+            walk(Walk::Key(key));
+        }
+        rest = next;
+
+        if let Ok((next, _)) = table_empty(rest) {
+            if depth == max_depth {
+                return Err(too_deep(rest));
+            }
+            rest = next;
+        } else if let Ok((next, brace)) = ws(tag("{"))(rest) {
+            if depth == max_depth {
+                return Err(too_deep(rest));
+            }
+            walk(Walk::Open(brace));
+            depth += 1;
+            rest = next;
+            continue;
+        } else {
+            rest = cut(context("value", scalar))(rest)?.0;
+        }
+
+        // close every table that ends after this value
+        loop {
+            match field_separator(rest) {
+                Ok((next, _)) => {
+                    rest = next;
+                    match preceded(spacing, tag("}"))(rest) {
+                        Ok((next, _)) => rest = next,
+                        Err(_) => break,
+                    }
+                }
+                Err(_) => {
+                    rest = cut(preceded(spacing, context("end of table ('}')", tag("}"))))(rest)?.0;
+                }
+            }
+            walk(Walk::Close);
+            depth -= 1;
+            // the spacing after the outermost table isn't part of it
+            if depth == 0 {
+                return Ok((rest, ()));
+            }
+            rest = spacing(rest)?.0;
+        }
+    }
+}
+
+/// Every top-level global assigned in a SavedVariables file, in file order.
+pub type Globals<'a> = IndexMap<Cow<'a, str>, Value<'a>>;
+
+/// What to do when a table constructor has the same key more than once. Lua itself keeps the last
+/// value, but a repeated key in a SavedVariables file usually means it was edited by hand or
+/// corrupted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicateKeys {
+    /// Fail with `ParseError::DuplicateKey`.
+    Error,
+    KeepFirst,
+    #[default]
+    KeepLast,
+    /// Replace the value with a [`Value::Duplicates`] of every value given for the key, in file
+    /// order.
+    CollectAll,
+}
+
+/// Options for [`globals_from_str_with`], [`global_from_str_with`] and [`from_str_with`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    pub duplicate_keys: DuplicateKeys,
+    /// Check the `-- [N]` comment that WoW writes after each array element against the element's
+    /// actual position, and warn about any that are missing or don't match. Those point to files
+    /// that were edited by hand or spliced together, or to arrays that lost entries.
+    pub check_index_comments: bool,
+    pub limits: Limits,
 }
 
-/// SavedVariables files begin with `<variable> = <table>`.
-/// Read it.
-fn initial_assignment(input: &str) -> IResult<Value> {
-    complete(map(ws(named_pair), |(_, v)| v))(input)
+/// The globals of a SavedVariables file, along with any problems found that did not prevent parsing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parsed<'a> {
+    pub globals: Globals<'a>,
+    pub warnings: Vec<Warning>,
 }
 
+/// A value deserialized from a SavedVariables file, along with any problems found that did not
+/// prevent it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deserialized<T> {
+    pub value: T,
+    pub warnings: Vec<Warning>,
+}
+
+/// Parse every global assignment in a SavedVariables file. If a global is assigned more than once,
+/// the last assignment wins (as it would in Lua).
+pub fn globals_from_str(s: &str) -> Result<Globals<'_>, ParseError> {
+    globals_from_str_with(s, ParseOptions::default()).map(|parsed| parsed.globals)
+}
+
+/// Parse every global assignment in a SavedVariables file, reporting every duplicated table key as
+/// a warning.
+pub fn globals_from_str_with(s: &str, options: ParseOptions) -> Result<Parsed<'_>, ParseError> {
+    let parser = ValueParser::new(options);
+    let (_, assignments) = parser.assignments(s).map_err(|e| parser.error(s, e))?;
+
+    Ok(Parsed {
+        globals: assignments.into_iter().collect(),
+        warnings: parser.warnings(s)?,
+    })
+}
+
+/// Deserialize the global `name` from a SavedVariables file, ignoring any others.
+///
+/// The whole file is checked for syntax errors, but only the requested global is deserialized. If
+/// it is assigned more than once, the last assignment wins (as it would in Lua).
+pub fn global_from_str<'a, T>(s: &'a str, name: &str) -> Result<T, ParseError>
+where
+    T: Deserialize<'a>,
+{
+    global_from_str_with(s, name, ParseOptions::default()).map(|global| global.value)
+}
+
+/// Like [`global_from_str`], reporting problems in the tables that were deserialized as warnings.
+/// Only the requested global is checked for those.
+pub fn global_from_str_with<'a, T>(
+    s: &'a str,
+    name: &str,
+    options: ParseOptions,
+) -> Result<Deserialized<T>, ParseError>
+where
+    T: Deserialize<'a>,
+{
+    let mut de = Deserializer::from_str(s).with_options(options);
+    let mut found = None;
+    while let Some(global) = de.next_global()? {
+        if global == name {
+            // only the last assignment's problems are reported
+            de.clear_warnings();
+            found = Some(de.deserialize_or_skip()?);
+        } else {
+            de.skip()?;
+        }
+    }
+    let value = found.unwrap_or_else(|| Err(ParseError::MissingGlobal(name.to_string())))?;
+    Ok(Deserialized {
+        value,
+        warnings: de.warnings()?,
+    })
+}
+
+/// Deserialize either a single bare value or a SavedVariables file containing exactly one global.
+/// Use [`global_from_str`] or [`globals_from_str`] for files with more than one.
 pub fn from_str<'a, T>(s: &'a str) -> Result<T, ParseError>
 where
     T: Deserialize<'a>,
 {
-    let (_, value) = alt((initial_assignment, complete(value)))(s)
-        .map_err(|v| ParseError::ValueError(format!("{}", v)))?;
-    let deserializer = ValueDeserializer(value);
-    let t = T::deserialize(deserializer)?;
+    from_str_with(s, ParseOptions::default()).map(|deserialized| deserialized.value)
+}
+
+/// Like [`from_str`], reporting problems in the tables that were deserialized as warnings.
+pub fn from_str_with<'a, T>(
+    s: &'a str,
+    options: ParseOptions,
+) -> Result<Deserialized<T>, ParseError>
+where
+    T: Deserialize<'a>,
+{
+    let mut de = Deserializer::from_str(s).with_options(options);
+    let is_assignment = preceded(spacing, pair(identifier, ws(tag("="))))(s).is_ok();
+    if !is_assignment {
+        let value = de.read()?;
+        de.end()?;
+        return Ok(Deserialized {
+            value,
+            warnings: de.warnings()?,
+        });
+    }
+
+    de.next_global()?;
+    let result = de.deserialize_or_skip()?;
+    let mut count = 1;
+    while de.next_global()?.is_some() {
+        de.skip()?;
+        count += 1;
+    }
+    match count {
+        1 => Ok(Deserialized {
+            value: result?,
+            warnings: de.warnings()?,
+        }),
+        count => Err(ParseError::GlobalCount(count)),
+    }
+}
 
-    Ok(t)
+/// A syntax error at the invalid UTF-8 at byte `offset` of `v`, which is at `decoded` in the lossy
+/// decoding of `v`.
+fn invalid_utf8(v: &[u8], offset: usize, decoded: usize) -> ParseError {
+    let mut err = SyntaxError::new(&String::from_utf8_lossy(v), decoded, "valid UTF-8".into());
+    err.offset = offset;
+    err.into()
+}
+
+/// Check that `v` is valid UTF-8, reporting the first invalid byte as a syntax error.
+fn validate_utf8(v: &[u8]) -> Result<&str, ParseError> {
+    std::str::from_utf8(v).map_err(|err| invalid_utf8(v, err.valid_up_to(), err.valid_up_to()))
+}
+
+/// Decode `v`, replacing invalid UTF-8 in strings and comments with U+FFFD. Anywhere else it is a
+/// syntax error. Valid input is borrowed as-is.
+///
+/// This is the decoding [`from_slice_lossy`] does, for callers that need to keep the text, e.g. to
+/// deserialize values that borrow from it.
+pub fn decode_lossy(v: &[u8]) -> Result<Cow<'_, str>, ParseError> {
+    let mut err = match std::str::from_utf8(v) {
+        Ok(s) => return Ok(Cow::Borrowed(s)),
+        Err(err) => err,
+    };
+    let mut decoded = String::with_capacity(v.len() + 16);
+    let mut scan = chunked::Scan::Code;
+    // how far `v` has been decoded, and how far it has been scanned for strings and comments
+    let (mut pos, mut scanned) = (0, 0);
+    loop {
+        let invalid = pos + err.valid_up_to();
+        while scanned < invalid {
+            scanned += scan.advance(&v[scanned..]).unwrap_or(1);
+        }
+        let valid = std::str::from_utf8(&v[pos..invalid]).unwrap();
+        if scan == chunked::Scan::Code {
+            return Err(invalid_utf8(v, invalid, decoded.len() + valid.len()));
+        }
+
+        decoded.push_str(valid);
+        decoded.push(char::REPLACEMENT_CHARACTER);
+        pos = err.error_len().map_or(v.len(), |len| invalid + len);
+        while scanned < pos {
+            scanned += scan.advance(&v[scanned..]).unwrap_or(1);
+        }
+        match std::str::from_utf8(&v[pos..]) {
+            Ok(rest) => {
+                decoded.push_str(rest);
+                return Ok(Cow::Owned(decoded));
+            }
+            Err(next) => err = next,
+        }
+    }
+}
+
+/// Like [`from_str`], for input that may not be valid UTF-8. The first invalid byte is reported as
+/// a syntax error at its offset.
+///
+/// Valid input is parsed in place, so `T` can borrow from it, which can't be done once invalid
+/// bytes have been replaced in a copy. Use [`from_slice_lossy`] (or [`decode_lossy`]) to read files
+/// from WoW, which may have invalid UTF-8 in their strings.
+pub fn from_slice<'a, T>(v: &'a [u8]) -> Result<T, ParseError>
+where
+    T: Deserialize<'a>,
+{
+    from_str(validate_utf8(v)?)
+}
+
+/// Like [`global_from_str`], for input that may not be valid UTF-8. See [`from_slice`].
+pub fn global_from_slice<'a, T>(v: &'a [u8], name: &str) -> Result<T, ParseError>
+where
+    T: Deserialize<'a>,
+{
+    global_from_str(validate_utf8(v)?, name)
+}
+
+/// Like [`from_slice`], but invalid UTF-8 in strings doesn't fail the whole file.
+///
+/// WoW writes strings out byte for byte, so addon-serialized binary data or broken locale text can
+/// end up in a SavedVariables file. Invalid bytes are replaced with U+FFFD in the strings (and
+/// comments) they appear in. Anywhere else they are still a syntax error. Since replacing them
+/// requires a decoded copy of the input, nothing can be borrowed from it.
+pub fn from_slice_lossy<T: DeserializeOwned>(v: &[u8]) -> Result<T, ParseError> {
+    from_str(&decode_lossy(v)?)
+}
+
+/// Like [`global_from_str`], replacing invalid UTF-8 in strings as [`from_slice_lossy`] does.
+pub fn global_from_slice_lossy<T: DeserializeOwned>(v: &[u8], name: &str) -> Result<T, ParseError> {
+    global_from_str(&decode_lossy(v)?, name)
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -201,8 +1109,40 @@ pub enum ParseError {
     Unknown,
     #[error("An error occurred during deserialization: {0}")]
     SerdeCustom(String),
-    #[error("A parse error occurred: {0}")]
-    ValueError(String),
+    #[error("A parse error occurred at {0}")]
+    SyntaxError(#[from] SyntaxError),
+    #[error("{error} (at {path})")]
+    AtPath { path: Path, error: Box<ParseError> },
+    #[error("No global named {0} was found")]
+    MissingGlobal(String),
+    #[error("Expected a single value or global, found {0} globals")]
+    GlobalCount(usize),
+    #[error("Duplicate table key at {0}")]
+    DuplicateKey(Warning),
+    #[error("Input ended before the end of {0}")]
+    UnexpectedEnd(String),
+    #[error("Unable to read input: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Input is too large: {0}")]
+    LimitExceeded(#[from] LimitExceeded),
+}
+
+impl ParseError {
+    /// Record that this error happened inside the entry `key`. Syntax errors already carry their
+    /// position, so they are left alone.
+    fn within(self, key: Key<'_>) -> ParseError {
+        match self {
+            ParseError::SyntaxError(_) => self,
+            ParseError::AtPath { mut path, error } => {
+                path.0.insert(0, key.into_owned());
+                ParseError::AtPath { path, error }
+            }
+            error => ParseError::AtPath {
+                path: Path(vec![key.into_owned()]),
+                error: Box::new(error),
+            },
+        }
+    }
 }
 
 impl de::Error for ParseError {
@@ -214,17 +1154,62 @@ impl de::Error for ParseError {
     }
 }
 
-pub struct ValueDeserializer<'a>(Value<'a>);
+pub struct ValueDeserializer<'de>(Value<'de>);
+
+/// Visit an integer that is too large for `i64` as a `u64` if possible, since far more types
+/// accept that than `i128`.
+fn visit_big_int<'de, V: Visitor<'de>>(v: i128, visitor: V) -> Result<V::Value, ParseError> {
+    match u64::try_from(v) {
+        Ok(v) => visitor.visit_u64(v),
+        Err(_) => visitor.visit_i128(v),
+    }
+}
+
+/// Lay out the entries of a table by their Lua index, as `ipairs` would see them if there were no
+/// holes. Integer keys fill in after the positional entries, with `nil` in any gaps so that they
+/// become `None` in a `Vec<Option<T>>`.
+fn sequence_of<'a>(
+    mut array: Vec<Value<'a>>,
+    named: Map<Key<'a>, Value<'a>>,
+) -> Result<Vec<Value<'a>>, ParseError> {
+    let positional = array.len();
+    let mut keyed = Vec::with_capacity(named.len());
+    for (key, value) in named {
+        let ix = match key {
+            Key::Int(ix) if ix >= 1 => ix as usize - 1,
+            key => {
+                return Err(ParseError::SerdeCustom(format!(
+                    "expected array table, found key {:?}",
+                    key
+                )))
+            }
+        };
+        // positional entries are assigned last in Lua, so they win over explicit keys. Lua doesn't
+        // store keys assigned `nil` at all, so they can't extend the sequence.
+        if ix >= positional && !matches!(value, Value::Nil) {
+            keyed.push((ix, value));
+        }
+    }
+
+    // the map is in no particular order, so size the sequence once up front
+    if let Some(last) = keyed.iter().map(|(ix, _)| *ix).max() {
+        array.resize_with(last + 1, || Value::Nil);
+    }
+    for (ix, value) in keyed {
+        array[ix] = value;
+    }
+    Ok(array)
+}
 
-impl<'de, 'a> IntoDeserializer<'de, ParseError> for Value<'a> {
-    type Deserializer = ValueDeserializer<'a>;
+impl<'de> IntoDeserializer<'de, ParseError> for Value<'de> {
+    type Deserializer = ValueDeserializer<'de>;
 
     fn into_deserializer(self) -> Self::Deserializer {
         ValueDeserializer(self)
     }
 }
 
-impl<'de, 'a> de::Deserializer<'de> for ValueDeserializer<'a> {
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'de> {
     type Error = ParseError;
 
     fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
@@ -234,13 +1219,13 @@ impl<'de, 'a> de::Deserializer<'de> for ValueDeserializer<'a> {
         match self.0 {
             Value::Nil => visitor.visit_unit(),
             Value::Int(v) => visitor.visit_i64(v),
+            Value::BigInt(v) => visit_big_int(v, visitor),
             Value::Bool(v) => visitor.visit_bool(v),
             Value::Float(v) => visitor.visit_f64(v),
             Value::String(Cow::Owned(v)) => visitor.visit_string(v),
-            Value::String(Cow::Borrowed(v)) => visitor.visit_str(v),
-            Value::Table(Table::Empty | Table::Array(_) | Table::FloatArray(_)) => {
-                self.deserialize_seq(visitor)
-            }
+            Value::String(Cow::Borrowed(v)) => visitor.visit_borrowed_str(v),
+            Value::Table(Table::Empty | Table::Array(_) | Table::FloatArray(_))
+            | Value::Duplicates(_) => self.deserialize_seq(visitor),
             Value::Table(Table::Named(_) | Table::MixedTable { .. }) => {
                 self.deserialize_map(visitor)
             }
@@ -259,12 +1244,18 @@ impl<'de, 'a> de::Deserializer<'de> for ValueDeserializer<'a> {
 
     fn deserialize_newtype_struct<V>(
         self,
-        _name: &'static str,
+        name: &'static str,
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
+        if name == RAW_LUA_TOKEN {
+            return Err(ParseError::SerdeCustom(
+                "RawLua can only be read from SavedVariables text".into(),
+            ));
+        }
+
         visitor.visit_newtype_struct(self)
     }
 
@@ -273,30 +1264,34 @@ impl<'de, 'a> de::Deserializer<'de> for ValueDeserializer<'a> {
         V: Visitor<'de>,
     {
         match self.0 {
-            Value::Table(Table::Empty) => visitor.visit_map(MapDeserializer::new(
-                std::iter::empty::<(Value<'a>, Value<'a>)>(),
+            Value::Table(Table::Empty) => {
+                visitor.visit_map(TableMapAccess::new(std::iter::empty()))
+            }
+            Value::Table(Table::Array(vec)) => visitor.visit_map(TableMapAccess::new(
+                vec.into_iter()
+                    .enumerate()
+                    .map(|(i, v)| (Key::Int(i as i64 + 1), v)),
+            )),
+            Value::Table(Table::FloatArray(vec)) => visitor.visit_map(TableMapAccess::new(
+                vec.into_iter()
+                    .enumerate()
+                    .map(|(i, v)| (Key::Int(i as i64 + 1), Value::Float(v))),
             )),
-            Value::Table(Table::Array(v)) if v.is_empty() => visitor.visit_map(
-                MapDeserializer::new(std::iter::empty::<(Value<'a>, Value<'a>)>()),
-            ),
-            Value::Table(Table::FloatArray(v)) if v.is_empty() => visitor.visit_map(
-                MapDeserializer::new(std::iter::empty::<(Value<'a>, Value<'a>)>()),
-            ),
             Value::Table(Table::Named(map)) => {
-                visitor.visit_map(MapDeserializer::new(map.into_iter()))
+                visitor.visit_map(TableMapAccess::new(map.into_iter()))
             }
 
             Value::Table(Table::MixedTable { array, named }) => {
-                visitor.visit_map(MapDeserializer::new(
+                visitor.visit_map(TableMapAccess::new(
                     array
                         .into_iter()
                         .enumerate()
-                        .map(|(i, v)| (Value::Int(i as i64), v))
-                        .chain(named.into_iter().map(|(k, v)| (Value::String(k), v))),
+                        .map(|(i, v)| (Key::Int(i as i64 + 1), v))
+                        .chain(named),
                 ))
             }
             value => Err(ParseError::SerdeCustom(format!(
-                "expected named or mixed table, found {:?}",
+                "expected table, found {:?}",
                 value
             ))),
         }
@@ -307,30 +1302,302 @@ impl<'de, 'a> de::Deserializer<'de> for ValueDeserializer<'a> {
     {
         match self.0 {
             Value::Table(Table::Empty) => {
-                visitor.visit_seq(SeqDeserializer::new(std::iter::empty::<Value<'a>>()))
-            }
-            Value::Table(Table::Named(map)) if map.is_empty() => {
-                visitor.visit_seq(SeqDeserializer::new(std::iter::empty::<Value<'a>>()))
-            }
-            Value::Table(Table::MixedTable { array, named })
-                if named.is_empty() && array.is_empty() =>
-            {
-                visitor.visit_seq(SeqDeserializer::new(std::iter::empty::<Value<'a>>()))
+                visitor.visit_seq(TableSeqAccess::new(std::iter::empty()))
             }
             Value::Table(Table::Array(vec)) => {
-                visitor.visit_seq(SeqDeserializer::new(vec.into_iter()))
+                visitor.visit_seq(TableSeqAccess::new(vec.into_iter()))
             }
             Value::Table(Table::FloatArray(vec)) => {
-                visitor.visit_seq(SeqDeserializer::new(vec.into_iter()))
+                visitor.visit_seq(TableSeqAccess::new(vec.into_iter().map(Value::Float)))
+            }
+            Value::Table(Table::Named(map)) => {
+                visitor.visit_seq(TableSeqAccess::new(sequence_of(vec![], map)?.into_iter()))
+            }
+            Value::Table(Table::MixedTable { array, named }) => {
+                visitor.visit_seq(TableSeqAccess::new(sequence_of(array, named)?.into_iter()))
             }
+            Value::Duplicates(values) => visitor.visit_seq(TableSeqAccess::new(values.into_iter())),
             _ => Err(ParseError::SerdeCustom("expected array table".into())),
         }
     }
 
+    /// Enums are externally tagged: unit variants are stored as their name, and all others as a
+    /// table with the variant name as its only key, e.g. `{ ["Raid"] = { ... } }`.
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            Value::String(variant) => visitor.visit_enum(variant.into_deserializer()),
+            Value::Table(Table::Named(map)) if map.len() == 1 => {
+                let (key, value) = map.into_iter().next().unwrap();
+                match key {
+                    Key::String(variant) => visitor.visit_enum(EnumDeserializer { variant, value }),
+                    key => Err(ParseError::SerdeCustom(format!(
+                        "expected enum variant name, found {:?}",
+                        key
+                    ))),
+                }
+            }
+            value => Err(ParseError::SerdeCustom(format!(
+                "expected enum variant name or a table with a single key, found {:?}",
+                value
+            ))),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct tuple
+        tuple_struct struct identifier ignored_any
+    }
+}
+
+/// A non-unit enum variant, stored as `{ [variant] = value }`.
+struct EnumDeserializer<'de> {
+    variant: Cow<'de, str>,
+    value: Value<'de>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumDeserializer<'de> {
+    type Error = ParseError;
+    type Variant = VariantDeserializer<'de>;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        let key = Key::String(self.variant);
+        let variant = seed.deserialize(KeyDeserializer(key.clone()))?;
+        Ok((
+            variant,
+            VariantDeserializer {
+                key,
+                value: self.value,
+            },
+        ))
+    }
+}
+
+struct VariantDeserializer<'de> {
+    key: Key<'de>,
+    value: Value<'de>,
+}
+
+impl<'de> de::VariantAccess<'de> for VariantDeserializer<'de> {
+    type Error = ParseError;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        // Lua tables can't hold `nil`, so a unit variant in table form can only have a placeholder.
+        match self.value {
+            Value::Bool(true) | Value::Table(Table::Empty) => Ok(()),
+            value => Err(ParseError::SerdeCustom(format!(
+                "expected unit variant, found {:?}",
+                value
+            ))
+            .within(self.key)),
+        }
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        let key = self.key;
+        seed.deserialize(ValueDeserializer(self.value))
+            .map_err(|err| err.within(key))
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let key = self.key;
+        de::Deserializer::deserialize_seq(ValueDeserializer(self.value), visitor)
+            .map_err(|err| err.within(key))
+    }
+
+    fn struct_variant<V>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let key = self.key;
+        de::Deserializer::deserialize_map(ValueDeserializer(self.value), visitor)
+            .map_err(|err| err.within(key))
+    }
+}
+
+/// Walks the entries of a table, recording which entry any error came from.
+struct TableMapAccess<'de, I> {
+    entries: I,
+    pending: Option<(Key<'de>, Value<'de>)>,
+}
+
+impl<'de, I> TableMapAccess<'de, I>
+where
+    I: Iterator<Item = (Key<'de>, Value<'de>)>,
+{
+    fn new(entries: I) -> Self {
+        TableMapAccess {
+            entries,
+            pending: None,
+        }
+    }
+}
+
+impl<'de, I> de::MapAccess<'de> for TableMapAccess<'de, I>
+where
+    I: Iterator<Item = (Key<'de>, Value<'de>)>,
+{
+    type Error = ParseError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        match self.entries.next() {
+            None => Ok(None),
+            Some((key, value)) => {
+                self.pending = Some((key.clone(), value));
+                seed.deserialize(KeyDeserializer(key))
+                    .map(Some)
+                    .map_err(|err| err.within(self.pending.take().unwrap().0))
+            }
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        let (key, value) = self
+            .pending
+            .take()
+            .ok_or_else(|| ParseError::SerdeCustom("value requested before key".into()))?;
+        match seed.deserialize(ValueDeserializer(value)) {
+            Ok(value) => Ok(value),
+            Err(err) => Err(err.within(key)),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        match self.entries.size_hint() {
+            (lower, Some(upper)) if lower == upper => Some(upper),
+            _ => None,
+        }
+    }
+}
+
+/// Walks the positional entries of a table, recording which index any error came from.
+struct TableSeqAccess<I> {
+    entries: I,
+    index: i64,
+}
+
+impl<I> TableSeqAccess<I> {
+    fn new(entries: I) -> Self {
+        TableSeqAccess { entries, index: 0 }
+    }
+}
+
+impl<'de, I> de::SeqAccess<'de> for TableSeqAccess<I>
+where
+    I: Iterator<Item = Value<'de>>,
+{
+    type Error = ParseError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        match self.entries.next() {
+            None => Ok(None),
+            Some(value) => {
+                // lua indices start at 1
+                self.index += 1;
+                seed.deserialize(ValueDeserializer(value))
+                    .map(Some)
+                    .map_err(|err| err.within(Key::Int(self.index)))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        match self.entries.size_hint() {
+            (lower, Some(upper)) if lower == upper => Some(upper),
+            _ => None,
+        }
+    }
+}
+
+pub struct KeyDeserializer<'de>(Key<'de>);
+
+impl<'de> IntoDeserializer<'de, ParseError> for Key<'de> {
+    type Deserializer = KeyDeserializer<'de>;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        KeyDeserializer(self)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for KeyDeserializer<'de> {
+    type Error = ParseError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            Key::Bool(v) => visitor.visit_bool(v),
+            Key::Int(v) => visitor.visit_i64(v),
+            Key::Float(v) => visitor.visit_f64(v),
+            Key::String(Cow::Owned(v)) => visitor.visit_string(v),
+            Key::String(Cow::Borrowed(v)) => visitor.visit_borrowed_str(v),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            Key::String(variant) => visitor.visit_enum(variant.into_deserializer()),
+            key => Err(ParseError::SerdeCustom(format!(
+                "expected enum variant name, found {:?}",
+                key
+            ))),
+        }
+    }
+
     forward_to_deserialize_any! {
         bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
-        bytes byte_buf unit unit_struct tuple
-        tuple_struct struct enum identifier ignored_any
+        bytes byte_buf option unit unit_struct seq tuple map
+        tuple_struct struct identifier ignored_any
     }
 }
 
@@ -425,8 +1692,151 @@ mod test {
         )
     }
 
+    #[test]
+    fn deserialize_escaped_string() {
+        let str: String = super::from_str(r#""say \"hi\"\n\\ \65\066\x43\z""#).unwrap();
+        assert_eq!(str, "say \"hi\"\n\\ ABCz");
+
+        let str: String = super::from_str(r#"'it\'s'"#).unwrap();
+        assert_eq!(str, "it's");
+
+        let str: String = super::from_str("\"line\\\r\nbreak\"").unwrap();
+        assert_eq!(str, "line\nbreak");
+    }
+
+    #[test]
+    fn parse_string_borrows_without_escapes() {
+        use std::borrow::Cow;
+
+        let (_, value) = super::value(r#""plain""#).unwrap();
+        assert!(matches!(
+            value,
+            super::Value::String(Cow::Borrowed("plain"))
+        ));
+
+        let (_, value) = super::value(r#""\t""#).unwrap();
+        assert!(matches!(value, super::Value::String(Cow::Owned(s)) if s == "\t"));
+    }
+
+    #[test]
+    fn parse_string_invalid_escape() {
+        assert!(super::value(r#""\256""#).is_err());
+    }
+
+    #[test]
+    fn parse_all_globals() {
+        let globals = super::globals_from_str(
+            r#"
+Profiling2_Storage = {
+    ["recordings"] = {},
+}
+Other_Character = "foo"
+Profiling2_Other = 3
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            globals.keys().collect::<Vec<_>>(),
+            vec!["Profiling2_Storage", "Other_Character", "Profiling2_Other"]
+        );
+
+        let value: usize = super::global_from_str("A = 1\nB = 2", "B").unwrap();
+        assert_eq!(value, 2);
+
+        let err = super::global_from_str::<usize>("A = 1\nB = 2", "C").unwrap_err();
+        assert!(matches!(err, super::ParseError::MissingGlobal(name) if name == "C"));
+    }
+
+    #[test]
+    fn deserialize_multiple_globals() {
+        let err = super::from_str::<usize>("A = 1\nB = 2").unwrap_err();
+        assert!(matches!(err, super::ParseError::GlobalCount(2)));
+
+        let value: usize = super::from_str("-- comment\nA = 1\n").unwrap();
+        assert_eq!(value, 1);
+    }
+
+    #[test]
+    fn syntax_error_position() {
+        let err =
+            super::globals_from_str("Foo = {\n\t[\"a\"] = 1,\n\t[\"b\"] = @,\n}\n").unwrap_err();
+        match err {
+            super::ParseError::SyntaxError(err) => {
+                assert_eq!((err.offset, err.line, err.column), (29, 3, 10));
+                assert_eq!(err.expected, "value");
+                assert_eq!(err.snippet, "\t[\"b\"] = @,\n         ^");
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+    }
+
+    #[test]
+    fn syntax_error_unterminated() {
+        let err = super::from_str::<Vec<String>>("{ 'abc', 'def }").unwrap_err();
+        match err {
+            super::ParseError::SyntaxError(err) => {
+                assert_eq!((err.line, err.column), (1, 16));
+                assert_eq!(err.expected, "closing quote");
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+
+        let err = super::from_str::<Vec<usize>>("{ 1, 2 3 }").unwrap_err();
+        match err {
+            super::ParseError::SyntaxError(err) => {
+                assert_eq!((err.line, err.column), (1, 8));
+                assert_eq!(err.expected, "end of table ('}')");
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+    }
+
+    #[test]
+    fn deserialize_error_path() {
+        use std::collections::HashMap;
+
+        #[derive(serde::Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Sketch {
+            count: u64,
+        }
+
+        #[derive(serde::Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Recording {
+            scripts: HashMap<String, Sketch>,
+        }
+
+        let err = super::from_str::<Vec<Recording>>(
+            r#"{
+                { scripts = { ["Foo:Bar"] = { count = 1 } } },
+                { scripts = { ["Foo:Bar"] = { count = "x" } } },
+            }"#,
+        )
+        .unwrap_err();
+
+        match &err {
+            super::ParseError::AtPath { path, .. } => {
+                assert_eq!(path.to_string(), r#"[2].scripts["Foo:Bar"].count"#)
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+        assert!(err
+            .to_string()
+            .ends_with(r#"(at [2].scripts["Foo:Bar"].count)"#));
+    }
+
     use nom::combinator::complete;
 
+    fn table(input: &str) -> super::IResult<super::Value> {
+        super::ValueParser::default().table(input)
+    }
+
+    fn named_pair(input: &str) -> super::IResult<(super::Key, super::Value)> {
+        super::ValueParser::default().named_pair(input)
+    }
+
     macro_rules! test_parse {
         ($name:ident, $parser:path, $input:expr) => {
             #[test]
@@ -445,15 +1855,82 @@ mod test {
         };
     }
 
-    test_parse!(
-        parse_string_key,
-        super::named_pair,
-        "[\"recordings\"] = 123"
-    );
+    #[test]
+    fn deserialize_int_keys() {
+        use std::collections::HashMap;
+
+        let result: HashMap<u32, f64> =
+            super::from_str("{ [5] = 0.25, [12] = 0.5, [1.0] = 1 }").unwrap();
+        assert_eq!(result, HashMap::from([(5, 0.25), (12, 0.5), (1, 1.0)]));
+    }
+
+    #[test]
+    fn parse_literal_keys() {
+        use super::{Key, Map, Table, Value};
+        use std::borrow::Cow;
+
+        let (_, value) = table(r#"{ [-2] = 1, [0.5] = 2, [true] = 3, ["x"] = 4, y = 5 }"#).unwrap();
+        assert_eq!(
+            value,
+            Value::Table(Table::Named(Map::from([
+                (Key::Int(-2), Value::Int(1)),
+                (Key::Float(0.5), Value::Int(2)),
+                (Key::Bool(true), Value::Int(3)),
+                (Key::String(Cow::Borrowed("x")), Value::Int(4)),
+                (Key::String(Cow::Borrowed("y")), Value::Int(5)),
+            ])))
+        );
+    }
+
+    #[test]
+    fn parse_mixed_table() {
+        use super::{Key, Map, Table, Value};
+        use std::borrow::Cow;
+
+        let (_, value) = table("{1, 2; foo = 3, [10] = 4; 'bar',}").unwrap();
+        assert_eq!(
+            value,
+            Value::Table(Table::MixedTable {
+                array: vec![
+                    Value::Int(1),
+                    Value::Int(2),
+                    Value::String(Cow::Borrowed("bar"))
+                ],
+                named: Map::from([
+                    (Key::String(Cow::Borrowed("foo")), Value::Int(3)),
+                    (Key::Int(10), Value::Int(4)),
+                ]),
+            })
+        );
+    }
+
+    #[test]
+    fn deserialize_mixed_table() {
+        use std::collections::HashMap;
+
+        #[derive(serde::Deserialize, PartialEq, Eq, Hash, Debug)]
+        #[serde(untagged)]
+        enum Key {
+            Index(u32),
+            Name(String),
+        }
+
+        let result: HashMap<Key, u32> = super::from_str("{ 5, 6, foo = 7 }").unwrap();
+        assert_eq!(
+            result,
+            HashMap::from([
+                (Key::Index(1), 5),
+                (Key::Index(2), 6),
+                (Key::Name("foo".to_string()), 7)
+            ])
+        );
+    }
+
+    test_parse!(parse_string_key, named_pair, "[\"recordings\"] = 123");
     test_parse!(parse_comment, super::comment, "-- foo\r\n");
     test_parse!(
         parse_encounter_table,
-        super::table,
+        table,
         r#"{
                                 ["mapId"] = 1571,
                                 ["success"] = true,
@@ -466,7 +1943,7 @@ mod test {
 
     test_parse!(
         parse_samples_table,
-        super::table,
+        table,
         r#"{
                    0.003000000026077032, -- [1]
                    0.005000000353902578, -- [2]
@@ -480,15 +1957,27 @@ mod test {
               }"#
     );
 
-    test_parse!(
-        parse_nested_tables,
-        super::table_array,
-        "{ 'abcd', 0, {{}}}"
-    );
+    test_parse!(parse_nested_tables, table, "{ 'abcd', 0, {{}}}");
     test_parse!(parse_single_string, super::value, "'abcd'");
 
     test_parse!(parse_string_bad_escape, super::value, r#""ab\d\"""#);
 
+    #[test]
+    fn deserialize_long_string() {
+        let str: String = super::from_str("[[\nfoo\\n\"bar\"]]").unwrap();
+        assert_eq!(str, "foo\\n\"bar\"");
+
+        let str: String = super::from_str("[==[a]]b]=]c]==]").unwrap();
+        assert_eq!(str, "a]]b]=]c");
+    }
+
+    test_parse!(
+        parse_block_comment,
+        table,
+        "{ --[[ a comment\n spanning lines ]] 1, --[==[ ]] ]==] 2 }"
+    );
+    test_parse!(parse_long_string_key, named_pair, "[ [[key]] ] = 1");
+
     #[test]
     fn parse_table_comment() {
         let (_, value) = complete(super::table_empty)(
@@ -499,4 +1988,433 @@ mod test {
 
         assert_eq!(value, super::Table::Empty);
     }
+
+    #[test]
+    fn deserialize_externally_tagged_enum() {
+        use serde::{de::IntoDeserializer, Deserialize};
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        enum Encounter {
+            Manual,
+            Raid { name: String, success: bool },
+            Delay(f64),
+        }
+
+        let globals = super::globals_from_str(
+            r#"
+            a = "Manual"
+            b = { ["Raid"] = { ["name"] = "Tindral", ["success"] = false } }
+            c = { Delay = 0.5 }
+            d = { Manual = true, Delay = 1 }
+            "#,
+        )
+        .unwrap();
+        let get = |name: &str| Encounter::deserialize(globals[name].clone().into_deserializer());
+
+        assert_eq!(get("a").unwrap(), Encounter::Manual);
+        assert_eq!(
+            get("b").unwrap(),
+            Encounter::Raid {
+                name: "Tindral".to_string(),
+                success: false
+            }
+        );
+        assert_eq!(get("c").unwrap(), Encounter::Delay(0.5));
+        assert!(get("d").is_err());
+    }
+
+    #[cfg(feature = "preserve_order")]
+    #[test]
+    fn parse_preserves_order() {
+        use super::{Key, Table, Value};
+
+        let (_, value) = table("{ z = 1, [3] = 2, a = 3, 'pos', m = 4 }").unwrap();
+        let keys = match value {
+            Value::Table(Table::MixedTable { named, .. }) => named.into_keys().collect::<Vec<_>>(),
+            value => panic!("unexpected value {:?}", value),
+        };
+        assert_eq!(
+            keys,
+            vec![
+                Key::String("z".into()),
+                Key::Int(3),
+                Key::String("a".into()),
+                Key::String("m".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_duplicate_keys() {
+        use super::{DuplicateKeys, Key, ParseError, ParseOptions, Table, Value};
+
+        let input = "Foo = {\n  a = 1,\n  b = 2,\n  a = { 3 },\n  a = 4,\n}";
+        let parse = |duplicate_keys| {
+            let options = ParseOptions {
+                duplicate_keys,
+                ..Default::default()
+            };
+            let parsed = super::globals_from_str_with(input, options)?;
+            let a = match &parsed.globals["Foo"] {
+                Value::Table(Table::Named(named)) => named[&Key::String("a".into())].clone(),
+                value => panic!("unexpected value {:?}", value),
+            };
+            Ok::<_, ParseError>((a, parsed.warnings))
+        };
+
+        let (a, warnings) = parse(DuplicateKeys::KeepLast).unwrap();
+        assert_eq!(a, Value::Int(4));
+        assert_eq!(warnings.len(), 2);
+        assert_eq!((warnings[0].line, warnings[0].column), (4, 3));
+        assert_eq!(
+            warnings[0].message,
+            "duplicate key a (first seen at line 2, column 3)"
+        );
+        assert_eq!((warnings[1].line, warnings[1].column), (5, 3));
+
+        let (a, _) = parse(DuplicateKeys::KeepFirst).unwrap();
+        assert_eq!(a, Value::Int(1));
+
+        let (a, _) = parse(DuplicateKeys::CollectAll).unwrap();
+        assert_eq!(
+            a,
+            Value::Duplicates(vec![
+                Value::Int(1),
+                Value::Table(Table::Array(vec![Value::Int(3)])),
+                Value::Int(4)
+            ])
+        );
+
+        match parse(DuplicateKeys::Error) {
+            Err(ParseError::DuplicateKey(warning)) => assert_eq!(warning.line, 4),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn deserialize_duplicate_keys() {
+        use super::{DuplicateKeys, ParseError, ParseOptions};
+        use serde::Deserialize;
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Foo<T> {
+            a: T,
+            b: i64,
+        }
+
+        let input = "Foo = {\n  a = 1,\n  b = 2,\n  a = 4,\n}\nBar = { a = 1, a = 2 }";
+        let with = |duplicate_keys| ParseOptions {
+            duplicate_keys,
+            ..Default::default()
+        };
+
+        let foo: Foo<i64> = super::global_from_str(input, "Foo").unwrap();
+        assert_eq!(foo, Foo { a: 4, b: 2 });
+
+        let foo =
+            super::global_from_str_with::<Foo<i64>>(input, "Foo", with(DuplicateKeys::KeepFirst))
+                .unwrap();
+        assert_eq!(foo.value, Foo { a: 1, b: 2 });
+        assert_eq!(foo.warnings.len(), 1);
+        assert_eq!(
+            foo.warnings[0].message,
+            "duplicate key a (first seen at line 2, column 3)"
+        );
+
+        let foo = super::global_from_str_with::<Foo<Vec<i64>>>(
+            input,
+            "Foo",
+            with(DuplicateKeys::CollectAll),
+        )
+        .unwrap();
+        assert_eq!(
+            foo.value,
+            Foo {
+                a: vec![1, 4],
+                b: 2
+            }
+        );
+        assert_eq!(foo.warnings.len(), 1);
+
+        match super::global_from_str_with::<Foo<i64>>(input, "Foo", with(DuplicateKeys::Error)) {
+            Err(ParseError::DuplicateKey(warning)) => assert_eq!(warning.line, 4),
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        let foo: Foo<i64> = super::from_str(&input[..input.find("\nBar").unwrap()]).unwrap();
+        assert_eq!(foo, Foo { a: 4, b: 2 });
+
+        // the first `a` can't be read, but is replaced before it needs to be
+        let nested: Vec<Foo<i64>> =
+            super::from_str("{ { a = 1, b = 2 }, { a = 'x', b = 3, a = 5 } }").unwrap();
+        assert_eq!(nested, vec![Foo { a: 1, b: 2 }, Foo { a: 5, b: 3 }]);
+    }
+
+    #[test]
+    fn parse_index_comments() {
+        use super::{Key, ParseOptions};
+        use std::collections::HashMap;
+
+        let input = "Foo = {\n\t{\n\t\t1, -- [1]\n\t\t2, -- [2]\n\t\t4, -- [4]\n\t\t5,\n\t}, -- [1]\n\t{ 1, 2 }, -- [2]\n\t[\"x\"] = 3,\n}";
+        let options = ParseOptions {
+            check_index_comments: true,
+            ..Default::default()
+        };
+        let parsed = super::globals_from_str_with(input, options).unwrap();
+        let warnings: Vec<_> = parsed
+            .warnings
+            .iter()
+            .map(|w| (w.line, w.message.as_str()))
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![
+                (5, "array element 3 is annotated as [4]"),
+                (6, "array element 4 has no index comment"),
+            ]
+        );
+
+        let parsed = super::globals_from_str_with(input, ParseOptions::default()).unwrap();
+        assert!(parsed.warnings.is_empty());
+
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        #[allow(dead_code)]
+        enum Entry {
+            Array(Vec<i64>),
+            Int(i64),
+        }
+        let deserialized =
+            super::global_from_str_with::<HashMap<Key, Entry>>(input, "Foo", options).unwrap();
+        let streamed: Vec<_> = deserialized
+            .warnings
+            .iter()
+            .map(|w| (w.line, w.message.as_str()))
+            .collect();
+        assert_eq!(streamed, warnings);
+    }
+
+    #[test]
+    fn parse_limits() {
+        use super::{Deserializer, LimitExceeded, Limits, ParseError, ParseOptions, Value};
+        use serde::Deserialize;
+
+        fn exceeded<T>(result: Result<T, ParseError>) -> Option<LimitExceeded> {
+            match result.err()? {
+                ParseError::LimitExceeded(exceeded) => Some(exceeded),
+                ParseError::AtPath { error, .. } => exceeded::<()>(Err(*error)),
+                _ => None,
+            }
+        }
+
+        let nested = |depth| format!("Foo = {}1{}", "{".repeat(depth), "}".repeat(depth));
+        let deep = nested(100_000);
+        assert_eq!(
+            exceeded(super::globals_from_str(&deep)),
+            Some(LimitExceeded::Depth(100))
+        );
+        assert_eq!(
+            exceeded(super::global_from_str::<Value>(&deep, "Foo")),
+            Some(LimitExceeded::Depth(100))
+        );
+        assert_eq!(
+            exceeded(super::from_str::<Value>(&deep["Foo = ".len()..])),
+            Some(LimitExceeded::Depth(100))
+        );
+        assert!(super::globals_from_str(&nested(100)).is_ok());
+        assert!(super::global_from_str::<Value>(&nested(100), "Foo").is_ok());
+
+        let limits = Limits {
+            max_elements: 4,
+            max_string_bytes: 3,
+            ..Default::default()
+        };
+        let options = ParseOptions {
+            limits,
+            ..Default::default()
+        };
+        let read =
+            |input| Value::deserialize(&mut Deserializer::from_str(input).with_limits(limits));
+        assert_eq!(
+            exceeded(super::globals_from_str_with(
+                "Foo = { 1, 2, 3, 4 }",
+                options
+            )),
+            Some(LimitExceeded::Elements(4))
+        );
+        assert_eq!(
+            exceeded(read("{ 1, 2, 3, 4 }")),
+            Some(LimitExceeded::Elements(4))
+        );
+        assert!(read("{ 1, 2, 3 }").is_ok());
+        assert_eq!(
+            exceeded(read("{ 'abcd' }")),
+            Some(LimitExceeded::StringBytes(3))
+        );
+        assert_eq!(
+            exceeded(super::globals_from_str_with("Foo = 'abcd'", options)),
+            Some(LimitExceeded::StringBytes(3))
+        );
+    }
+
+    #[test]
+    fn deserialize_tables_by_index() {
+        use serde::{de::IntoDeserializer, Deserialize};
+        use std::collections::HashMap;
+
+        let globals = super::globals_from_str(
+            "a = { 'x', 'y' }\nb = { [3] = 0.5, [1] = 0.25 }\nc = { 1, 2, [5] = 3 }\nd = { x = 1 }",
+        )
+        .unwrap();
+        let value = |name: &str| globals[name].clone().into_deserializer();
+
+        let map = HashMap::<u32, String>::deserialize(value("a")).unwrap();
+        assert_eq!(
+            map,
+            HashMap::from([(1, "x".to_string()), (2, "y".to_string())])
+        );
+
+        let vec = Vec::<Option<f64>>::deserialize(value("b")).unwrap();
+        assert_eq!(vec, vec![Some(0.25), None, Some(0.5)]);
+
+        let vec = Vec::<Option<u32>>::deserialize(value("c")).unwrap();
+        assert_eq!(vec, vec![Some(1), Some(2), None, None, Some(3)]);
+
+        assert!(Vec::<u32>::deserialize(value("d")).is_err());
+
+        // the same conversions apply when reading text directly
+        let vec: Vec<Option<f64>> = super::from_str("{ [3] = 0.5, [1] = 0.25 }").unwrap();
+        assert_eq!(vec, vec![Some(0.25), None, Some(0.5)]);
+
+        let map: HashMap<u32, String> = super::from_str("{ 'x', 'y' }").unwrap();
+        assert_eq!(
+            map,
+            HashMap::from([(1, "x".to_string()), (2, "y".to_string())])
+        );
+    }
+
+    #[test]
+    fn deserialize_nil_holes() {
+        let vec: Vec<Option<u32>> = super::from_str("{ 1, nil, 3, nil }").unwrap();
+        assert_eq!(vec, vec![Some(1), None, Some(3), None]);
+
+        let vec: Vec<Option<u32>> = super::from_str("{ [2] = 2, [4] = 4, [6] = nil }").unwrap();
+        assert_eq!(vec, vec![None, Some(2), None, Some(4)]);
+
+        let vec: Vec<Option<u32>> = super::from_str("{ nil, 2, [4] = 4 }").unwrap();
+        assert_eq!(vec, vec![None, Some(2), None, Some(4)]);
+
+        let err = super::from_str::<Vec<u32>>("{ 1, nil, 3 }").unwrap_err();
+        assert!(err.to_string().ends_with("(at [2])"), "{}", err);
+    }
+
+    #[test]
+    fn deserialize_invalid_utf8() {
+        use std::collections::HashMap;
+
+        let input =
+            b"Data = { [\"good\"] = \"ok\", [\"bad\"] = \"a\xffb\", [\"escaped\"] = \"\\255\" }";
+        let data: HashMap<String, String> = super::global_from_slice_lossy(input, "Data").unwrap();
+        assert_eq!(data["good"], "ok");
+        assert_eq!(data["bad"], "a\u{fffd}b");
+        assert_eq!(data["escaped"], "\u{fffd}");
+
+        let data: String = super::from_slice_lossy(b"[[\xc3\x28]] -- \xff").unwrap();
+        assert_eq!(data, "\u{fffd}(");
+
+        assert_eq!(
+            super::decode_lossy(b"x = '\xff'\n").unwrap(),
+            "x = '\u{fffd}'\n"
+        );
+        assert!(matches!(
+            super::decode_lossy(b"x = 1"),
+            Ok(std::borrow::Cow::Borrowed("x = 1"))
+        ));
+
+        // valid input is borrowed
+        let data: HashMap<&str, &str> =
+            super::global_from_slice(b"Data = { a = 'b' }", "Data").unwrap();
+        assert_eq!(data["a"], "b");
+
+        match super::global_from_slice::<HashMap<String, String>>(input, "Data") {
+            Err(super::ParseError::SyntaxError(err)) => {
+                assert_eq!(err.offset, 38);
+                assert_eq!(err.expected, "valid UTF-8");
+            }
+            result => panic!("expected a syntax error, got {:?}", result),
+        }
+
+        // outside of strings, invalid bytes are still an error
+        match super::from_slice_lossy::<Vec<String>>(b"{ '\xff', \xff }") {
+            Err(super::ParseError::SyntaxError(err)) => {
+                assert_eq!((err.offset, err.column), (7, 8))
+            }
+            result => panic!("expected a syntax error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn deserialize_borrowed_str() {
+        use std::{borrow::Cow, collections::HashMap};
+
+        use serde::{de::IntoDeserializer, Deserialize};
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Script<'a> {
+            name: &'a str,
+            #[serde(borrow)]
+            path: Cow<'a, str>,
+        }
+
+        let input = r#"{ ["name"] = "frame", ["path"] = "Interface\\AddOns" }"#;
+        let script: Script = super::from_str(input).unwrap();
+        assert_eq!(script.name, "frame");
+        assert!(matches!(script.path, Cow::Owned(_)));
+
+        let input = r#"{ ["name"] = "frame", ["path"] = "Interface" }"#;
+        let value = super::value(input).unwrap().1;
+        let script = Script::deserialize(value.into_deserializer()).unwrap();
+        assert_eq!(script.name, "frame");
+        assert!(matches!(script.path, Cow::Borrowed("Interface")));
+
+        let keys: HashMap<&str, u32> = super::from_str(r#"{ ["a"] = 1, b = 2 }"#).unwrap();
+        assert_eq!(keys, HashMap::from([("a", 1), ("b", 2)]));
+    }
+
+    #[test]
+    fn deserialize_large_ints() {
+        let v: u64 = super::from_str("18446744073709551615").unwrap();
+        assert_eq!(v, u64::MAX);
+
+        let v: i128 = super::from_str("-170141183460469231731687303715884105728").unwrap();
+        assert_eq!(v, i128::MIN);
+
+        let globals = super::globals_from_str("a = 9223372036854775808\nb = 1e3").unwrap();
+        assert_eq!(globals["a"], super::Value::BigInt(1 << 63));
+        assert_eq!(globals["b"], super::Value::Float(1000.0));
+    }
+
+    #[test]
+    fn deserialize_non_finite_floats() {
+        let values: Vec<f64> = super::from_str(
+            "{ 1.#INF, -1.#INF, inf, -inf, math.huge, -math.huge, 1.#IND, -1.#IND, 1.#QNAN, nan }",
+        )
+        .unwrap();
+
+        assert_eq!(
+            values[..6],
+            [
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::INFINITY,
+                f64::NEG_INFINITY
+            ]
+        );
+        assert!(values[6..].iter().all(|v| v.is_nan()));
+
+        // these are only special on their own
+        assert!(super::from_str::<f64>("info").is_err());
+    }
 }
@@ -0,0 +1,156 @@
+//! Limits on how much of a file is read, so that broken or hostile input fails cleanly rather than
+//! overflowing the stack or exhausting memory.
+
+use std::cell::Cell;
+
+use crate::Value;
+
+/// Upper bounds on the input a decoder will accept.
+///
+/// The defaults are far beyond anything WoW writes, and only stop pathological input. Use
+/// [`Limits::none`] for input that is trusted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// How deeply tables may be nested. Readers recurse into nested tables, so this is what keeps
+    /// them from running out of stack.
+    pub max_depth: usize,
+    /// How many values (including tables) may be read in total.
+    pub max_elements: usize,
+    /// The longest string that may be read, in bytes.
+    pub max_string_bytes: usize,
+    /// An estimate of how much memory may be allocated for the values read: the size of each value,
+    /// plus the bytes of any string that had to be copied.
+    pub max_allocation: usize,
+}
+
+impl Limits {
+    /// No limits at all.
+    pub const fn none() -> Limits {
+        Limits {
+            max_depth: usize::MAX,
+            max_elements: usize::MAX,
+            max_string_bytes: usize::MAX,
+            max_allocation: usize::MAX,
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_depth: 100,
+            max_elements: 1 << 24,
+            max_string_bytes: 1 << 26,
+            max_allocation: 1 << 30,
+        }
+    }
+}
+
+/// The limit that the input went over.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitExceeded {
+    #[error("tables are nested more than {0} deep")]
+    Depth(usize),
+    #[error("there are more than {0} values")]
+    Elements(usize),
+    #[error("a string is longer than {0} bytes")]
+    StringBytes(usize),
+    #[error("the values take up more than {0} bytes")]
+    Allocation(usize),
+}
+
+/// Keeps track of what has been read so far against a set of [`Limits`].
+///
+/// This is shared with other decoders that build `Value`s, so that they count the same way.
+#[derive(Debug, Default)]
+pub struct Budget {
+    limits: Limits,
+    depth: Cell<usize>,
+    elements: Cell<usize>,
+    allocated: Cell<usize>,
+}
+
+impl Budget {
+    pub fn new(limits: Limits) -> Budget {
+        Budget {
+            limits,
+            ..Default::default()
+        }
+    }
+
+    /// Enter a nested table. Every successful call must be matched by a call to [`Budget::leave`].
+    pub fn enter(&self) -> Result<(), LimitExceeded> {
+        if self.depth.get() >= self.limits.max_depth {
+            return Err(LimitExceeded::Depth(self.limits.max_depth));
+        }
+        self.depth.set(self.depth.get() + 1);
+        Ok(())
+    }
+
+    pub fn leave(&self) {
+        self.depth.set(self.depth.get().saturating_sub(1));
+    }
+
+    /// How many more tables may be entered.
+    pub fn depth_left(&self) -> usize {
+        self.limits.max_depth.saturating_sub(self.depth.get())
+    }
+
+    /// Count one value.
+    pub fn element(&self) -> Result<(), LimitExceeded> {
+        self.charge(1, std::mem::size_of::<Value>())
+    }
+
+    /// Count a string value of `len` bytes. Only strings that had to be copied take up memory.
+    pub fn string(&self, len: usize, copied: bool) -> Result<(), LimitExceeded> {
+        if len > self.limits.max_string_bytes {
+            return Err(LimitExceeded::StringBytes(self.limits.max_string_bytes));
+        }
+        self.element()?;
+        self.charge(0, if copied { len } else { 0 })
+    }
+
+    /// Count `elements` values taking up `bytes` of memory all at once, e.g. for a copy of a table
+    /// that was already read.
+    pub fn charge(&self, elements: usize, bytes: usize) -> Result<(), LimitExceeded> {
+        let elements = self.elements.get().saturating_add(elements);
+        if elements > self.limits.max_elements {
+            return Err(LimitExceeded::Elements(self.limits.max_elements));
+        }
+        let allocated = self.allocated.get().saturating_add(bytes);
+        if allocated > self.limits.max_allocation {
+            return Err(LimitExceeded::Allocation(self.limits.max_allocation));
+        }
+        self.elements.set(elements);
+        self.allocated.set(allocated);
+        Ok(())
+    }
+
+    /// The number of values and the bytes charged so far.
+    pub fn used(&self) -> (usize, usize) {
+        (self.elements.get(), self.allocated.get())
+    }
+
+    pub(crate) fn usage(&self) -> Usage {
+        Usage {
+            depth: self.depth.get(),
+            elements: self.elements.get(),
+            allocated: self.allocated.get(),
+        }
+    }
+
+    /// Go back to an earlier [`Budget::usage`], e.g. when a value is read again.
+    pub(crate) fn restore(&self, usage: Usage) {
+        self.depth.set(usage.depth);
+        self.elements.set(usage.elements);
+        self.allocated.set(usage.allocated);
+    }
+}
+
+/// Everything a [`Budget`] has counted at some point.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Usage {
+    depth: usize,
+    elements: usize,
+    allocated: usize,
+}
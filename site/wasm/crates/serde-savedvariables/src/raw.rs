@@ -0,0 +1,160 @@
+//! Lazily parsed values, captured as the source text they were read from.
+
+use std::fmt::{Display, Formatter};
+
+use nom::{combinator::eof, error::context, sequence::terminated};
+use serde::{
+    de::{self, Visitor},
+    Deserialize, Serialize,
+};
+
+use crate::{ParseError, Value, ValueParser};
+
+/// Name used to recognize `RawLua` in `Deserializer::deserialize_newtype_struct`, and when
+/// serializing it.
+pub(crate) const RAW_LUA_TOKEN: &str = "$serde_savedvariables::RawLua";
+
+/// The unparsed source text of a single value.
+///
+/// Deserializing a `RawLua` only checks the value's syntax, without building it. The value can be
+/// parsed later with [`RawLua::parse`]. This only works when reading text with [`crate::from_str`],
+/// [`crate::global_from_str`] or [`crate::Deserializer`], since a `Value` no longer knows its
+/// source. Use `#[serde(borrow)]` on fields of this type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawLua<'a>(&'a str);
+
+impl<'a> RawLua<'a> {
+    /// The source text of the value, without surrounding whitespace or comments.
+    pub fn get(&self) -> &'a str {
+        self.0
+    }
+
+    pub fn is_table(&self) -> bool {
+        self.0.starts_with('{')
+    }
+
+    /// Deserialize the captured value. Error positions are relative to the captured text.
+    pub fn parse<T>(&self) -> Result<T, ParseError>
+    where
+        T: Deserialize<'a>,
+    {
+        crate::from_str(self.0)
+    }
+
+    /// Parse the captured value into a `Value` tree.
+    pub fn to_value(&self) -> Result<Value<'a>, ParseError> {
+        let parser = ValueParser::default();
+        let result = terminated(|i| parser.value(i), context("end of input", eof))(self.0);
+        result
+            .map(|(_, value)| value)
+            .map_err(|err| parser.error(self.0, err))
+    }
+}
+
+impl<'a> Display for RawLua<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+struct RawLuaVisitor;
+
+impl<'de> Visitor<'de> for RawLuaVisitor {
+    type Value = RawLua<'de>;
+
+    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+        formatter.write_str("a value read directly from SavedVariables text")
+    }
+
+    fn visit_borrowed_str<E>(self, v: &'de str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(RawLua(v))
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for RawLua<'a> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_newtype_struct(RAW_LUA_TOKEN, RawLuaVisitor)
+    }
+}
+
+/// [`crate::to_string`] writes the captured text back out unchanged, keeping its original
+/// indentation. Other formats get it as a string.
+impl<'a> Serialize for RawLua<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_newtype_struct(RAW_LUA_TOKEN, self.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use pretty_assertions::assert_eq;
+    use serde::Deserialize;
+
+    use super::RawLua;
+    use crate::{from_str, global_from_str, ParseError, Value};
+
+    #[derive(Deserialize, Debug)]
+    struct Recording<'a> {
+        encounter: String,
+        #[serde(borrow)]
+        data: RawLua<'a>,
+    }
+
+    #[test]
+    fn capture_raw_table() {
+        let input = r#"Storage = {
+            {
+                ["encounter"] = "raid",
+                ["data"] = { ["calls"] = 3, -- [1]
+                    ["times"] = { 1.5, 2 } },
+            },
+        }"#;
+        let result: Vec<Recording> = global_from_str(input, "Storage").unwrap();
+
+        assert_eq!(result[0].encounter, "raid");
+        assert_eq!(
+            result[0].data.get(),
+            "{ [\"calls\"] = 3, -- [1]\n                    [\"times\"] = { 1.5, 2 } }"
+        );
+        assert!(result[0].data.is_table());
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Data {
+            calls: u32,
+            times: Vec<f64>,
+        }
+        assert_eq!(
+            result[0].data.parse::<Data>().unwrap(),
+            Data {
+                calls: 3,
+                times: vec![1.5, 2.0]
+            }
+        );
+    }
+
+    #[test]
+    fn capture_raw_scalar() {
+        let raw: HashMap<String, RawLua> = from_str(r#"{ a = "x\ty", b = 12 }"#).unwrap();
+
+        assert_eq!(raw["a"].get(), r#""x\ty""#);
+        assert_eq!(raw["a"].parse::<String>().unwrap(), "x\ty");
+        assert_eq!(raw["b"].to_value().unwrap(), Value::Int(12));
+    }
+
+    #[test]
+    fn capture_raw_checks_syntax() {
+        let result = from_str::<RawLua>("{ 1, 2 3 }");
+        assert!(matches!(result, Err(ParseError::SyntaxError(_))));
+    }
+}
@@ -0,0 +1,291 @@
+//! Best-effort reading of an array from a truncated or corrupted SavedVariables file.
+
+use std::ops::Range;
+
+use nom::{
+    bytes::complete::tag, combinator::recognize, error::VerboseError, sequence::preceded, Offset,
+};
+use serde::Deserialize;
+
+use crate::{
+    chunked::Scan, field_separator, spacing, ws, Deserializer, IResult, ParseError, ValueParser,
+};
+
+/// A part of the input that was skipped while recovering an array.
+#[derive(Debug)]
+pub struct Skipped {
+    /// Byte range of the skipped text in the input.
+    pub range: Range<usize>,
+    /// Why it was skipped.
+    pub error: ParseError,
+}
+
+/// The elements of an array that could be read, and the parts of it that were skipped.
+#[derive(Debug)]
+pub struct Recovered<T> {
+    pub values: Vec<T>,
+    pub skipped: Vec<Skipped>,
+}
+
+/// Match the ` = {` that starts an assigned table.
+fn table_start(input: &str) -> IResult<&str> {
+    preceded(ws(tag("=")), tag("{"))(input)
+}
+
+/// Whether a match failed only because it reached the end of the input, so that more input may
+/// still complete it.
+fn ran_out(err: &nom::Err<VerboseError<&str>>) -> bool {
+    match err {
+        nom::Err::Error(e) | nom::Err::Failure(e) => {
+            e.errors.iter().any(|(rest, _)| rest.is_empty())
+        }
+        nom::Err::Incomplete(_) => true,
+    }
+}
+
+/// Looks for the table at a path: a global assigned at the start of a line, then one
+/// `["key"] = {` after another, as WoW writes them. Each key has to be an entry of the table found
+/// before it, so keys in nested tables, strings or comments don't match. The input may be searched
+/// a piece at a time.
+pub(crate) struct TableSearch {
+    /// The global, then each key as it's written.
+    needles: Vec<String>,
+    /// How many of `needles` have been found. The search is inside that many tables.
+    found: usize,
+    scan: Scan,
+    depth: usize,
+    line_start: bool,
+    /// The table that should contain the next key was closed without it.
+    missing: bool,
+}
+
+impl TableSearch {
+    pub(crate) fn new(path: &[&str]) -> TableSearch {
+        let needles = path
+            .iter()
+            .enumerate()
+            .map(|(ix, part)| match ix {
+                0 => part.to_string(),
+                _ => format!("[\"{}\"]", part),
+            })
+            .collect();
+        TableSearch {
+            needles,
+            found: 0,
+            scan: Scan::Code,
+            depth: 0,
+            line_start: true,
+            missing: false,
+        }
+    }
+
+    /// Search `input`, which carries on from the input already searched. Returns where the contents
+    /// of the table start, or otherwise how much of `input` has been searched. The rest could be
+    /// the start of a match, so it has to be searched again with more input after it.
+    pub(crate) fn search(&mut self, input: &str) -> Result<usize, usize> {
+        let bytes = input.as_bytes();
+        let mut pos = 0;
+        while pos < bytes.len() && !self.missing {
+            let rest = &bytes[pos..];
+            let code = self.scan == Scan::Code;
+            let needle = self.needles.get(self.found).map(String::as_bytes);
+            if let Some(needle) = needle.filter(|_| code && self.depth == self.found) {
+                if rest.len() < needle.len() && needle.starts_with(rest) {
+                    return Err(pos);
+                }
+                if rest.starts_with(needle) && (self.found > 0 || self.line_start) {
+                    match table_start(&input[pos + needle.len()..]) {
+                        Ok((after, _)) => {
+                            pos = input.len() - after.len();
+                            self.found += 1;
+                            self.depth += 1;
+                            self.line_start = false;
+                            if self.found == self.needles.len() {
+                                return Ok(pos);
+                            }
+                            continue;
+                        }
+                        Err(err) if ran_out(&err) => return Err(pos),
+                        Err(_) => {}
+                    }
+                }
+            }
+
+            let len = match self.scan.advance(rest) {
+                Some(len) => len,
+                None => return Err(pos),
+            };
+            if code && self.scan == Scan::Code {
+                match rest[0] {
+                    b'{' => self.depth += 1,
+                    b'}' if self.depth == self.found && self.found > 0 => self.missing = true,
+                    b'}' => self.depth = self.depth.saturating_sub(1),
+                    _ => {}
+                }
+            }
+            self.line_start = rest[len - 1] == b'\n';
+            pos += len;
+        }
+        Err(input.len())
+    }
+}
+
+/// Locate the table at `path` without parsing anything around it, since that may be what's broken.
+pub(crate) fn open_table<'a>(s: &'a str, path: &[&str]) -> Option<&'a str> {
+    TableSearch::new(path)
+        .search(s)
+        .ok()
+        .map(|start| &s[start..])
+}
+
+/// Find where to continue after a broken element starting at `element`. WoW writes one element per
+/// line at a fixed indentation, so this is the next line that opens an element at the same
+/// indentation, or the line that closes the array one level up. Without that layout, the rest of
+/// the input is skipped.
+fn resync<'a>(s: &'a str, element: &'a str) -> &'a str {
+    let start = s.offset(element);
+    let line_start = s[..start].rfind('\n').map(|ix| ix + 1).unwrap_or(0);
+    let indent = &s[line_start..start];
+    let end = &element[element.len()..];
+    if !indent.chars().all(char::is_whitespace) {
+        return end;
+    }
+
+    let next = format!("\n{}{{", indent);
+    let close = indent
+        .char_indices()
+        .last()
+        .map(|(ix, _)| format!("\n{}}}", &indent[..ix]));
+    // resume at the brace that ends either needle
+    [Some(next), close]
+        .into_iter()
+        .flatten()
+        .filter_map(|needle| element.find(&needle).map(|ix| ix + needle.len() - 1))
+        .min()
+        .map_or(end, |ix| &element[ix..])
+}
+
+/// Deserialize the elements of the array at `path`, skipping any that can't be read.
+///
+/// `path` is the name of a global followed by the keys leading to the array, e.g.
+/// `["Profiling2_Storage", "recordings"]`. Unlike [`crate::global_from_str`], nothing outside the
+/// array has to be valid. After a syntax error, reading continues at the next element, which is
+/// found by its indentation. Elements that are valid Lua but don't deserialize are skipped as a
+/// whole. Fails only if the array can't be found.
+pub fn recover_array_from_str<'a, T>(s: &'a str, path: &[&str]) -> Result<Recovered<T>, ParseError>
+where
+    T: Deserialize<'a>,
+{
+    let mut rest = open_table(s, path).ok_or_else(|| ParseError::MissingGlobal(path.join(".")))?;
+    let mut recovered = Recovered {
+        values: vec![],
+        skipped: vec![],
+    };
+
+    loop {
+        rest = spacing(rest).map(|(rest, _)| rest).unwrap_or(rest);
+        if rest.is_empty() || rest.starts_with('}') {
+            return Ok(recovered);
+        }
+
+        // checked against the same depth limit as deserializing it will be, so that input nested too
+        // deeply is reported as such whatever the element's type
+        let parser = ValueParser::default();
+        let (after, span) = match recognize(|i| parser.skip_value(i))(rest) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                let resume = resync(s, rest);
+                recovered.skipped.push(Skipped {
+                    range: s.offset(rest)..s.offset(resume),
+                    error: parser.error(s, err),
+                });
+                rest = resume;
+                continue;
+            }
+        };
+        match Deserializer::from_span(s, span).read() {
+            Ok(value) => recovered.values.push(value),
+            Err(error) => recovered.skipped.push(Skipped {
+                range: s.offset(span)..s.offset(after),
+                error,
+            }),
+        }
+
+        rest = field_separator(after)
+            .map(|(after, _)| after)
+            .unwrap_or(after);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use serde::Deserialize;
+
+    use super::recover_array_from_str;
+    use crate::ParseError;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Recording {
+        name: String,
+    }
+
+    const PATH: &[&str] = &["Storage", "recordings"];
+
+    #[test]
+    fn recover_corrupted_element() {
+        let input = "Storage = {\n\t[\"recordings\"] = {\n\t\t{\n\t\t\t[\"name\"] = \"a\",\n\t\t}, -- [1]\n\t\t{\n\t\t\t[\"name\"] = \"b\" \"c\",\n\t\t}, -- [2]\n\t\t{\n\t\t\t[\"name\"] = 3,\n\t\t}, -- [3]\n\t\t{\n\t\t\t[\"name\"] = \"d\",\n\t\t}, -- [4]\n\t},\n\t[\"broken\"] = ,\n}\n";
+        let recovered = recover_array_from_str::<Recording>(input, PATH).unwrap();
+
+        assert_eq!(
+            recovered.values,
+            vec![
+                Recording { name: "a".into() },
+                Recording { name: "d".into() }
+            ]
+        );
+        assert_eq!(recovered.skipped.len(), 2);
+        let second = input.find("\t\t{\n\t\t\t[\"name\"] = \"b\"").unwrap() + 2;
+        let third = input.find("\t\t{\n\t\t\t[\"name\"] = 3").unwrap() + 2;
+        assert_eq!(recovered.skipped[0].range, second..third);
+        assert!(matches!(
+            recovered.skipped[0].error,
+            ParseError::SyntaxError(ref err) if err.line == 7
+        ));
+        assert_eq!(recovered.skipped[1].range.start, third);
+        assert!(matches!(
+            recovered.skipped[1].error,
+            ParseError::AtPath { .. }
+        ));
+    }
+
+    #[test]
+    fn recover_truncated_file() {
+        let input = "Storage = {\n\t[\"recordings\"] = {\n\t\t{\n\t\t\t[\"name\"] = \"a\",\n\t\t}, -- [1]\n\t\t{\n\t\t\t[\"name\"] = \"b";
+        let recovered = recover_array_from_str::<Recording>(input, PATH).unwrap();
+
+        assert_eq!(recovered.values, vec![Recording { name: "a".into() }]);
+        assert_eq!(recovered.skipped.len(), 1);
+        assert_eq!(recovered.skipped[0].range.end, input.len());
+
+        assert!(matches!(
+            recover_array_from_str::<Recording>(input, &["Other", "recordings"]),
+            Err(ParseError::MissingGlobal(_))
+        ));
+    }
+
+    #[test]
+    fn recover_only_entries_of_the_table() {
+        let input = "Storage = {\n\t[\"old\"] = {\n\t\t[\"recordings\"] = {\n\t\t\t{\n\t\t\t\t[\"name\"] = \"old\",\n\t\t\t},\n\t\t},\n\t},\n\t[\"note\"] = \"[\\\"recordings\\\"] = {\",\n\t-- [\"recordings\"] = {\n\t[\"recordings\"] = {\n\t\t{\n\t\t\t[\"name\"] = \"a\",\n\t\t},\n\t},\n}\n";
+        let recovered = recover_array_from_str::<Recording>(input, PATH).unwrap();
+        assert_eq!(recovered.values, vec![Recording { name: "a".into() }]);
+        assert!(recovered.skipped.is_empty());
+
+        // a key after the end of its table isn't in it
+        let input = "Storage = {\n}\nOther = {\n\t[\"recordings\"] = {\n\t\t{},\n\t},\n}\n";
+        assert!(matches!(
+            recover_array_from_str::<Recording>(input, PATH),
+            Err(ParseError::MissingGlobal(_))
+        ));
+    }
+}
@@ -0,0 +1,877 @@
+//! Writing values as SavedVariables text, laid out the way WoW writes it.
+
+use std::fmt::{Debug, Display, Write};
+
+use serde::{
+    ser::{self, Impossible},
+    Serialize,
+};
+
+use crate::{raw::RAW_LUA_TOKEN, Key};
+
+#[derive(thiserror::Error, Debug)]
+pub enum SerializeError {
+    #[error("An error occurred during serialization: {0}")]
+    Custom(String),
+    #[error("{0} cannot be written as a SavedVariables value")]
+    Unsupported(&'static str),
+    #[error("{0} cannot be used as a table key")]
+    InvalidKey(String),
+}
+
+impl ser::Error for SerializeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        SerializeError::Custom(msg.to_string())
+    }
+}
+
+/// Write `value` as a bare Lua value, e.g. for [`crate::from_str`] to read back.
+///
+/// Tables are laid out as WoW lays them out: one entry per line, indented with tabs, with keys in
+/// brackets (`["key"] = value,`) and each positional entry followed by its index (`value, -- [1]`).
+/// Sequences become positional entries, and structs and maps keyed entries. Map entries keyed by
+/// the next free index are written positionally, which Lua treats the same. Entries whose value is
+/// `nil` are left out, since Lua doesn't store them, but `nil` elements of a sequence are kept to
+/// preserve the positions of the ones after them. Enums follow the same convention as reading:
+/// unit variants are strings and other variants `{ ["Variant"] = value }`.
+///
+/// Floats are written in the shortest form that reads back exactly, and infinity as `math.huge`.
+/// NaN has no Lua literal, so it can't be written.
+pub fn to_string<T: ?Sized + Serialize>(value: &T) -> Result<String, SerializeError> {
+    to_string_indented(value, 0)
+}
+
+/// Like [`to_string`], for a value that starts on a line indented `indent` tabs deep.
+pub(crate) fn to_string_indented<T: ?Sized + Serialize>(
+    value: &T,
+    indent: usize,
+) -> Result<String, SerializeError> {
+    let mut serializer = Serializer {
+        indent,
+        ..Default::default()
+    };
+    value.serialize(&mut serializer)?;
+    Ok(serializer.output)
+}
+
+/// Write `value` as an assignment to the global `name`, in the format of a SavedVariables file.
+/// Nothing is written if `value` is `nil`, as WoW doesn't save those globals. See [`to_string`].
+pub fn global_to_string<T: ?Sized + Serialize>(
+    name: &str,
+    value: &T,
+) -> Result<String, SerializeError> {
+    if !crate::error::is_identifier(name) {
+        return Err(SerializeError::InvalidKey(format!("{:?}", name)));
+    }
+
+    let mut serializer = Serializer {
+        pending: Some(Pending {
+            prefix: format!("{} = ", name),
+            skip_nil: true,
+        }),
+        ..Default::default()
+    };
+    value.serialize(&mut serializer)?;
+    if !serializer.skipped {
+        serializer.output.push('\n');
+    }
+    Ok(serializer.output)
+}
+
+/// Write `s` as a double-quoted Lua string. Control characters are escaped, with decimal escapes
+/// padded to three digits so that a digit after them can't be mistaken for part of them. Everything
+/// else is written as-is, as WoW does.
+fn write_string(output: &mut String, s: &str) {
+    output.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if c.is_ascii_control() => {
+                let _ = write!(output, "\\{:03}", c as u8);
+            }
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+}
+
+/// Format a float so that it reads back as the same float.
+fn format_float<F: Into<f64> + Debug + Copy>(v: F) -> Result<String, SerializeError> {
+    let f: f64 = v.into();
+    match f {
+        f if f.is_nan() => Err(SerializeError::Unsupported("NaN")),
+        f64::INFINITY => Ok("math.huge".into()),
+        f64::NEG_INFINITY => Ok("-math.huge".into()),
+        // Debug is the shortest representation that round-trips, and keeps the `.0` of whole
+        // numbers so that they are still read as floats
+        _ => Ok(format!("{:?}", v)),
+    }
+}
+
+/// Write `key` the way it appears in a table constructor, e.g. `["key"]` or `[3]`.
+fn write_key(output: &mut String, key: &Key) -> Result<(), SerializeError> {
+    output.push('[');
+    match key {
+        Key::String(s) => write_string(output, s),
+        Key::Int(i) => output.push_str(&i.to_string()),
+        Key::Float(f) => output.push_str(&format_float(*f)?),
+        Key::Bool(b) => output.push_str(&b.to_string()),
+    }
+    output.push(']');
+    Ok(())
+}
+
+/// Text to write before the next value. If the value is `nil`, it may be dropped along with the
+/// text, as Lua doesn't store keys assigned `nil`.
+struct Pending {
+    prefix: String,
+    skip_nil: bool,
+}
+
+#[derive(Default)]
+struct Serializer {
+    output: String,
+    indent: usize,
+    pending: Option<Pending>,
+    /// Set when the last value was a `nil` that was dropped.
+    skipped: bool,
+    /// Set while writing the source of a `RawLua`.
+    raw: bool,
+}
+
+impl Serializer {
+    /// Start a value, writing any text that should come before it.
+    fn begin(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            self.output.push_str(&pending.prefix);
+        }
+    }
+
+    fn write(&mut self, text: &str) -> Result<(), SerializeError> {
+        self.begin();
+        self.output.push_str(text);
+        Ok(())
+    }
+
+    /// A line break followed by the current indentation.
+    fn line(&self) -> String {
+        format!("\n{}", "\t".repeat(self.indent))
+    }
+
+    fn open(&mut self) {
+        self.begin();
+        self.output.push('{');
+        self.indent += 1;
+    }
+
+    fn close(&mut self) {
+        self.indent -= 1;
+        let line = self.line();
+        self.output.push_str(&line);
+        self.output.push('}');
+    }
+
+    fn table(&mut self) -> Table<'_> {
+        self.open();
+        Table {
+            ser: self,
+            index: 0,
+            key: None,
+            variant: false,
+        }
+    }
+
+    /// Open `{ ["variant"] = {`, for an enum variant that holds a table.
+    fn variant_table(&mut self, variant: &str) -> Table<'_> {
+        self.open();
+        let mut prefix = self.line();
+        prefix.push('[');
+        write_string(&mut prefix, variant);
+        prefix.push_str("] = ");
+        self.pending = Some(Pending {
+            prefix,
+            skip_nil: false,
+        });
+        self.open();
+        Table {
+            ser: self,
+            index: 0,
+            key: None,
+            variant: true,
+        }
+    }
+}
+
+/// Writes the entries of a table.
+struct Table<'a> {
+    ser: &'a mut Serializer,
+    /// Number of positional entries written so far.
+    index: i64,
+    /// Key of a map entry whose value comes next.
+    key: Option<Key<'static>>,
+    /// Whether this is the inner table of `{ ["variant"] = { ... } }`.
+    variant: bool,
+}
+
+impl<'a> Table<'a> {
+    /// Write an entry, positionally if `key` is `None` or the next free index.
+    fn entry<T: ?Sized + Serialize>(
+        &mut self,
+        key: Option<Key>,
+        value: &T,
+    ) -> Result<(), SerializeError> {
+        let positional = match &key {
+            None => true,
+            Some(Key::Int(i)) => *i == self.index + 1,
+            Some(_) => false,
+        };
+        let mut prefix = self.ser.line();
+        if let (false, Some(key)) = (positional, &key) {
+            write_key(&mut prefix, key)?;
+            prefix.push_str(" = ");
+        }
+        self.ser.pending = Some(Pending {
+            prefix,
+            skip_nil: key.is_some(),
+        });
+
+        value.serialize(&mut *self.ser)?;
+        if std::mem::take(&mut self.ser.skipped) {
+            return Ok(());
+        }
+        if positional {
+            self.index += 1;
+            let _ = write!(self.ser.output, ", -- [{}]", self.index);
+        } else {
+            self.ser.output.push(',');
+        }
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), SerializeError> {
+        self.ser.close();
+        if self.variant {
+            self.ser.output.push(',');
+            self.ser.close();
+        }
+        Ok(())
+    }
+}
+
+impl<'a> ser::Serializer for &'a mut Serializer {
+    type Ok = ();
+    type Error = SerializeError;
+
+    type SerializeSeq = Table<'a>;
+    type SerializeTuple = Table<'a>;
+    type SerializeTupleStruct = Table<'a>;
+    type SerializeTupleVariant = Table<'a>;
+    type SerializeMap = Table<'a>;
+    type SerializeStruct = Table<'a>;
+    type SerializeStructVariant = Table<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), SerializeError> {
+        self.write(if v { "true" } else { "false" })
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), SerializeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), SerializeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), SerializeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), SerializeError> {
+        self.write(&v.to_string())
+    }
+
+    /// Integers beyond `i64` are written exactly, and read back as `Value::BigInt`.
+    fn serialize_i128(self, v: i128) -> Result<(), SerializeError> {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), SerializeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), SerializeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), SerializeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), SerializeError> {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<(), SerializeError> {
+        self.write(&v.to_string())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), SerializeError> {
+        self.write(&format_float(v)?)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), SerializeError> {
+        self.write(&format_float(v)?)
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), SerializeError> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), SerializeError> {
+        self.begin();
+        if self.raw {
+            self.output.push_str(v);
+        } else {
+            write_string(&mut self.output, v);
+        }
+        Ok(())
+    }
+
+    /// Bytes are written as an array of numbers, since they may not be valid UTF-8.
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), SerializeError> {
+        use ser::SerializeSeq;
+
+        let mut table = self.table();
+        for b in v {
+            table.serialize_element(b)?;
+        }
+        table.end()
+    }
+
+    fn serialize_none(self) -> Result<(), SerializeError> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), SerializeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), SerializeError> {
+        match self.pending.take() {
+            Some(Pending { skip_nil: true, .. }) => {
+                self.skipped = true;
+                Ok(())
+            }
+            pending => {
+                self.pending = pending;
+                self.write("nil")
+            }
+        }
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), SerializeError> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<(), SerializeError> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<(), SerializeError> {
+        if name == RAW_LUA_TOKEN {
+            self.raw = true;
+            let result = value.serialize(&mut *self);
+            self.raw = false;
+            return result;
+        }
+
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<(), SerializeError> {
+        let mut table = self.table();
+        table.entry(Some(Key::String(variant.into())), value)?;
+        table.end()
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Table<'a>, SerializeError> {
+        Ok(self.table())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Table<'a>, SerializeError> {
+        Ok(self.table())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Table<'a>, SerializeError> {
+        Ok(self.table())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Table<'a>, SerializeError> {
+        Ok(self.variant_table(variant))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Table<'a>, SerializeError> {
+        Ok(self.table())
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Table<'a>, SerializeError> {
+        Ok(self.table())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Table<'a>, SerializeError> {
+        Ok(self.variant_table(variant))
+    }
+}
+
+impl<'a> ser::SerializeSeq for Table<'a> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.entry(None, value)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        Table::end(self)
+    }
+}
+
+impl<'a> ser::SerializeTuple for Table<'a> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.entry(None, value)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        Table::end(self)
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for Table<'a> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.entry(None, value)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        Table::end(self)
+    }
+}
+
+impl<'a> ser::SerializeTupleVariant for Table<'a> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.entry(None, value)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        Table::end(self)
+    }
+}
+
+impl<'a> ser::SerializeMap for Table<'a> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Self::Error> {
+        self.key = Some(key.serialize(KeySerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| SerializeError::Custom("value given before key".into()))?;
+        self.entry(Some(key), value)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        Table::end(self)
+    }
+}
+
+impl<'a> ser::SerializeStruct for Table<'a> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.entry(Some(Key::String(key.into())), value)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        Table::end(self)
+    }
+}
+
+impl<'a> ser::SerializeStructVariant for Table<'a> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.entry(Some(Key::String(key.into())), value)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        Table::end(self)
+    }
+}
+
+/// Turns a map key into a table `Key`, rejecting anything Lua can't use as one.
+struct KeySerializer;
+
+impl KeySerializer {
+    fn invalid(what: &str) -> SerializeError {
+        SerializeError::InvalidKey(what.to_string())
+    }
+}
+
+impl ser::Serializer for KeySerializer {
+    type Ok = Key<'static>;
+    type Error = SerializeError;
+
+    type SerializeSeq = Impossible<Key<'static>, SerializeError>;
+    type SerializeTuple = Impossible<Key<'static>, SerializeError>;
+    type SerializeTupleStruct = Impossible<Key<'static>, SerializeError>;
+    type SerializeTupleVariant = Impossible<Key<'static>, SerializeError>;
+    type SerializeMap = Impossible<Key<'static>, SerializeError>;
+    type SerializeStruct = Impossible<Key<'static>, SerializeError>;
+    type SerializeStructVariant = Impossible<Key<'static>, SerializeError>;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        Ok(Key::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        Ok(Key::Int(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok, Self::Error> {
+        i64::try_from(v)
+            .map(Key::Int)
+            .map_err(|_| Self::invalid(&v.to_string()))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i128(v.into())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> {
+        i64::try_from(v)
+            .map(Key::Int)
+            .map_err(|_| Self::invalid(&v.to_string()))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        if v.is_nan() {
+            return Err(Self::invalid("NaN"));
+        }
+        Ok(Key::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        Ok(Key::String(v.to_string().into()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        Ok(Key::String(v.to_string().into()))
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Err(Self::invalid("a byte array"))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Err(Self::invalid("nil"))
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Err(Self::invalid("nil"))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Err(Self::invalid("nil"))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        Err(Self::invalid("a table"))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Err(Self::invalid("a table"))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Err(Self::invalid("a table"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Err(Self::invalid("a table"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Err(Self::invalid("a table"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Err(Self::invalid("a table"))
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Err(Self::invalid("a table"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Err(Self::invalid("a table"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use pretty_assertions::assert_eq;
+    use serde::{Deserialize, Serialize};
+
+    use super::{global_to_string, to_string, SerializeError};
+    use crate::{from_str, RawLua, Value};
+
+    #[derive(Serialize)]
+    enum Kind {
+        Raid,
+        Dungeon { level: u32 },
+    }
+
+    #[derive(Serialize)]
+    struct Recording<'a> {
+        encounter: &'a str,
+        missing: Option<u32>,
+        times: Vec<f64>,
+        holes: Vec<Option<i64>>,
+        kinds: (Kind, Kind),
+        empty: Vec<u8>,
+        ids: BTreeMap<u64, bool>,
+    }
+
+    #[test]
+    fn serialize_wow_layout() {
+        let recording = Recording {
+            encounter: "a \"b\"\n\x01",
+            missing: None,
+            times: vec![1.5, 2.0, f64::INFINITY, 1e-7],
+            holes: vec![Some(1), None, Some(3)],
+            kinds: (Kind::Raid, Kind::Dungeon { level: 15 }),
+            empty: vec![],
+            ids: BTreeMap::from([(1, true), (5, false), (1 << 40, true)]),
+        };
+
+        assert_eq!(
+            global_to_string("Storage", &recording).unwrap(),
+            r#"Storage = {
+	["encounter"] = "a \"b\"\n\001",
+	["times"] = {
+		1.5, -- [1]
+		2.0, -- [2]
+		math.huge, -- [3]
+		1e-7, -- [4]
+	},
+	["holes"] = {
+		1, -- [1]
+		nil, -- [2]
+		3, -- [3]
+	},
+	["kinds"] = {
+		"Raid", -- [1]
+		{
+			["Dungeon"] = {
+				["level"] = 15,
+			},
+		}, -- [2]
+	},
+	["empty"] = {
+	},
+	["ids"] = {
+		true, -- [1]
+		[5] = false,
+		[1099511627776] = true,
+	},
+}
+"#
+        );
+        assert_eq!(to_string(&Some(3)).unwrap(), "3");
+        assert_eq!(global_to_string("Storage", &None::<u32>).unwrap(), "");
+    }
+
+    #[test]
+    fn serialize_round_trip() {
+        let input = r#"{
+            ["name"] = "tab\tquote\"back\\slash\0001",
+            ["list"] = { 1.5, 2, [4] = 3, ["x"] = { {}, true } },
+            ["big"] = 18446744073709551615,
+            [2.5] = -1,
+            [false] = "no",
+        }"#;
+        let value: Value = from_str(input).unwrap();
+        let written = to_string(&value).unwrap();
+        assert_eq!(from_str::<Value>(&written).unwrap(), value);
+
+        #[derive(Deserialize, Serialize, Debug, PartialEq)]
+        struct Recording<'a> {
+            encounter: String,
+            #[serde(borrow)]
+            data: RawLua<'a>,
+        }
+        let input = "{ [\"encounter\"] = \"raid\", [\"data\"] = { 1, 2 } }";
+        let recording: Recording = from_str(input).unwrap();
+        let written = to_string(&recording).unwrap();
+        assert_eq!(
+            written,
+            "{\n\t[\"encounter\"] = \"raid\",\n\t[\"data\"] = { 1, 2 },\n}"
+        );
+        assert_eq!(from_str::<Recording>(&written).unwrap(), recording);
+    }
+
+    #[test]
+    fn serialize_errors() {
+        assert!(matches!(
+            to_string(&f64::NAN),
+            Err(SerializeError::Unsupported(_))
+        ));
+        assert!(matches!(
+            to_string(&BTreeMap::from([((1, 2), 3)])),
+            Err(SerializeError::InvalidKey(_))
+        ));
+        assert!(matches!(
+            global_to_string("not a name", &1),
+            Err(SerializeError::InvalidKey(_))
+        ));
+    }
+}
@@ -0,0 +1,976 @@
+//! A serde `Deserializer` that reads SavedVariables text directly, without building a `Value`
+//! tree first.
+
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+};
+
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::one_of,
+    combinator::{eof, map, recognize},
+    error::context,
+    sequence::{preceded, terminated},
+    Offset,
+};
+use serde::{
+    de::{self, DeserializeSeed, IntoDeserializer, Visitor},
+    forward_to_deserialize_any,
+};
+
+use crate::{
+    boolean, field_key, identifier, index_comment, limits::Usage, nil, raw::RAW_LUA_TOKEN, scalar,
+    sequence_of, spacing, string, table_empty, value_serde::VALUE_TOKEN, visit_big_int, ws,
+    Duplicate, DuplicateKeys, IResult, Key, KeyDeserializer, Limits, Map, ParseError, ParseOptions,
+    Table, Value, ValueDeserializer, ValueParser, Walk, Warning,
+};
+
+/// How a table starts, which decides whether it is read as a sequence or as a map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FirstEntry {
+    Empty,
+    Positional,
+    Keyed,
+}
+
+/// A position in the input to go back to, along with what had been counted and found up to it.
+struct Checkpoint<'de> {
+    rest: &'de str,
+    usage: Usage,
+    duplicates: usize,
+    index_mismatches: usize,
+}
+
+/// Deserializes values directly from SavedVariables text.
+///
+/// A visitor can't be told to forget an entry, so a table with a duplicated key can only be read
+/// as [`DuplicateKeys::KeepLast`] or [`DuplicateKeys::CollectAll`] ask by starting over and
+/// building that table as a `Value` first. [`Deserializer::read`] does that, while deserializing
+/// with `T::deserialize(&mut deserializer)` fails with `ParseError::DuplicateKey` instead.
+pub struct Deserializer<'de> {
+    /// The complete input, used to report error positions.
+    input: &'de str,
+    /// The part of the input that has not been read yet.
+    rest: &'de str,
+    /// Keeps count against the limits, builds tables that can't be read in order, and collects
+    /// the problems found along the way.
+    parser: ValueParser<'de>,
+    /// Offsets of the tables to build as a `Value` before visiting them, because of their
+    /// duplicated keys.
+    rebuild: HashSet<usize>,
+    /// Whether a table was added to `rebuild` since the last attempt to read a value.
+    retry: bool,
+    /// Whether each table that was looked over has a keyed entry, by offset. Looking over a table
+    /// notes every table in it, so that no part of the input is looked over more than once.
+    keyed: HashMap<usize, bool>,
+}
+
+impl<'de> Deserializer<'de> {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(input: &'de str) -> Self {
+        Deserializer::from_span(input, input)
+    }
+
+    /// Read only `span`, which must be a slice of `input`.
+    pub(crate) fn from_span(input: &'de str, span: &'de str) -> Self {
+        Deserializer {
+            input,
+            rest: span,
+            parser: ValueParser::default(),
+            rebuild: HashSet::new(),
+            retry: false,
+            keyed: HashMap::new(),
+        }
+    }
+
+    /// Read with `limits` instead of the default ones.
+    pub fn with_limits(self, limits: Limits) -> Self {
+        let options = self.parser.options;
+        self.with_options(ParseOptions { limits, ..options })
+    }
+
+    /// Read with `options` instead of the default ones.
+    pub fn with_options(mut self, options: ParseOptions) -> Self {
+        self.parser = ValueParser::new(options);
+        self
+    }
+
+    /// Deserialize the value that comes next, going back over it if a table in it has to be
+    /// built first because of a duplicated key.
+    pub fn read<T>(&mut self) -> Result<T, ParseError>
+    where
+        T: de::Deserialize<'de>,
+    {
+        loop {
+            let checkpoint = self.checkpoint();
+            let result = T::deserialize(&mut *self);
+            // an entry may have failed only because a later one with the same key was to replace
+            // it or be collected with it
+            let retry = result.is_err()
+                && (std::mem::take(&mut self.retry)
+                    || self.rebuild_duplicate_keys(checkpoint.rest));
+            if !retry {
+                return result;
+            }
+            self.rewind(checkpoint);
+        }
+    }
+
+    /// Every problem found so far in what was deserialized (but not in skipped values), in file
+    /// order. With [`DuplicateKeys::Error`], this fails with the first duplicated key instead.
+    pub fn warnings(&self) -> Result<Vec<Warning>, ParseError> {
+        self.parser.warnings(self.input)
+    }
+
+    pub(crate) fn clear_warnings(&self) {
+        self.parser.duplicates.borrow_mut().clear();
+        self.parser.index_mismatches.borrow_mut().clear();
+    }
+
+    /// Check that nothing but whitespace and comments is left in the input.
+    pub fn end(&mut self) -> Result<(), ParseError> {
+        self.parse(preceded(spacing, context("end of input", eof)))
+            .map(|_| ())
+    }
+
+    fn parse<O>(
+        &mut self,
+        mut parser: impl FnMut(&'de str) -> IResult<'de, O>,
+    ) -> Result<O, ParseError> {
+        match parser(self.rest) {
+            Ok((rest, output)) => {
+                self.rest = rest;
+                Ok(output)
+            }
+            Err(err) => Err(self.parser.error(self.input, err)),
+        }
+    }
+
+    /// Like [`Deserializer::parse`], for the parsers of a `ValueParser`, which count what they read
+    /// against the limits.
+    fn parse_counted<O>(
+        &mut self,
+        parser: impl FnOnce(&ValueParser<'de>, &'de str) -> IResult<'de, O>,
+    ) -> Result<O, ParseError> {
+        match parser(&self.parser, self.rest) {
+            Ok((rest, output)) => {
+                self.rest = rest;
+                Ok(output)
+            }
+            Err(err) => Err(self.parser.error(self.input, err)),
+        }
+    }
+
+    fn checkpoint(&self) -> Checkpoint<'de> {
+        Checkpoint {
+            rest: self.rest,
+            usage: self.parser.budget.usage(),
+            duplicates: self.parser.duplicates.borrow().len(),
+            index_mismatches: self.parser.index_mismatches.borrow().len(),
+        }
+    }
+
+    fn rewind(&mut self, checkpoint: Checkpoint<'de>) {
+        self.rest = checkpoint.rest;
+        self.parser.budget.restore(checkpoint.usage);
+        self.parser
+            .duplicates
+            .borrow_mut()
+            .truncate(checkpoint.duplicates);
+        self.parser
+            .index_mismatches
+            .borrow_mut()
+            .truncate(checkpoint.index_mismatches);
+    }
+
+    /// Read the `name =` that starts the next global assignment, or `None` at the end of the
+    /// input.
+    pub(crate) fn next_global(&mut self) -> Result<Option<Cow<'de, str>>, ParseError> {
+        self.skip_spacing()?;
+        if self.rest.is_empty() {
+            return Ok(None);
+        }
+        self.parse(context(
+            "global assignment",
+            terminated(identifier, ws(tag("="))),
+        ))
+        .map(Some)
+    }
+
+    /// Consume the value that comes next without building it.
+    pub(crate) fn skip(&mut self) -> Result<(), ParseError> {
+        self.skip_spacing()?;
+        self.parse_counted(ValueParser::skip_value)
+    }
+
+    /// Deserialize the value that comes next. If that fails, the value is skipped instead so that
+    /// reading can go on after it, and the error is returned as the result.
+    pub(crate) fn deserialize_or_skip<T>(&mut self) -> Result<Result<T, ParseError>, ParseError>
+    where
+        T: de::Deserialize<'de>,
+    {
+        let checkpoint = self.checkpoint();
+        match self.read() {
+            Ok(value) => Ok(Ok(value)),
+            Err(err) => {
+                self.rewind(checkpoint);
+                self.skip()?;
+                Ok(Err(err))
+            }
+        }
+    }
+
+    /// Add every table in the value at the start of `input` that has a key more than once that
+    /// should be kept last or collected to `rebuild`, returning whether any were new. This looks
+    /// over the whole value once, so it is only for after reading it failed.
+    fn rebuild_duplicate_keys(&mut self, input: &'de str) -> bool {
+        let options = self.parser.options;
+        if !matches!(
+            options.duplicate_keys,
+            DuplicateKeys::KeepLast | DuplicateKeys::CollectAll
+        ) {
+            return false;
+        }
+
+        let mut open: Vec<(usize, HashSet<Key>, bool)> = vec![];
+        let mut found = vec![];
+        let walked = ValueParser::new(options).walk_value(input, |walk| match walk {
+            Walk::Open(brace) => open.push((self.input.offset(brace), HashSet::new(), false)),
+            Walk::Key(key) => {
+                let (_, keys, duplicated) = open.last_mut().expect("an open table");
+                *duplicated |= !keys.insert(key);
+            }
+            Walk::Close => {
+                let (offset, _, duplicated) = open.pop().expect("an open table");
+                if duplicated {
+                    found.push(offset);
+                }
+            }
+        });
+        if walked.is_err() {
+            return false;
+        }
+        let mut new = false;
+        for offset in found {
+            new |= self.rebuild.insert(offset);
+        }
+        new
+    }
+
+    fn skip_spacing(&mut self) -> Result<(), ParseError> {
+        self.parse(spacing)
+    }
+
+    /// Skip whitespace, then consume `c` if it is next.
+    fn eat(&mut self, c: char) -> Result<bool, ParseError> {
+        self.skip_spacing()?;
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Skip whitespace, then check whether a table comes next.
+    fn at_table(&mut self) -> Result<bool, ParseError> {
+        self.skip_spacing()?;
+        Ok(self.rest.starts_with('{'))
+    }
+
+    /// Look at the first entry of the table that comes next, without consuming anything. Only
+    /// that entry is looked at, so that no part of the input is read more than once.
+    fn first_entry(&self) -> Result<FirstEntry, ParseError> {
+        let (entry, _) = preceded(tag("{"), spacing)(self.rest)
+            .map_err(|err| self.parser.error(self.input, err))?;
+        Ok(if entry.starts_with('}') {
+            FirstEntry::Empty
+        } else if field_key(entry).is_ok() {
+            FirstEntry::Keyed
+        } else {
+            FirstEntry::Positional
+        })
+    }
+
+    /// Whether the table that comes next has a keyed entry anywhere in it, without consuming
+    /// anything.
+    fn has_keyed_entry(&mut self) -> Result<bool, ParseError> {
+        let start = self.input.offset(self.rest);
+        if let Some(&keyed) = self.keyed.get(&start) {
+            return Ok(keyed);
+        }
+
+        let (input, noted) = (self.input, &mut self.keyed);
+        let mut open = vec![];
+        self.parser
+            .walk_value(self.rest, |walk| match walk {
+                Walk::Open(brace) => open.push((input.offset(brace), false)),
+                Walk::Key(_) => open.last_mut().expect("an open table").1 = true,
+                Walk::Close => {
+                    let (offset, keyed) = open.pop().expect("an open table");
+                    noted.insert(offset, keyed);
+                }
+            })
+            .map_err(|err| self.parser.error(self.input, err))?;
+        Ok(self.keyed[&start])
+    }
+
+    /// Count a value that was read against the limits.
+    fn count(&self, value: &Value) -> Result<(), ParseError> {
+        let budget = &self.parser.budget;
+        Ok(match value {
+            Value::String(s) => budget.string(s.len(), matches!(s, Cow::Owned(_))),
+            _ => budget.element(),
+        }?)
+    }
+
+    fn visit_scalar<V>(&mut self, visitor: V) -> Result<V::Value, ParseError>
+    where
+        V: Visitor<'de>,
+    {
+        let value = self.parse(context("value", scalar))?;
+        self.count(&value)?;
+        match value {
+            Value::Nil => visitor.visit_unit(),
+            Value::Bool(v) => visitor.visit_bool(v),
+            Value::Int(v) => visitor.visit_i64(v),
+            Value::BigInt(v) => visit_big_int(v, visitor),
+            Value::Float(v) => visitor.visit_f64(v),
+            Value::String(Cow::Owned(v)) => visitor.visit_string(v),
+            Value::String(Cow::Borrowed(v)) => visitor.visit_borrowed_str(v),
+            Value::Table(_) | Value::Duplicates(_) => unreachable!("tables are not scalars"),
+        }
+    }
+
+    /// Walk the table that comes next, consuming any entries the visitor leaves unread.
+    fn visit_table<V, F>(&mut self, visit: F) -> Result<V, ParseError>
+    where
+        F: FnOnce(&mut TableAccess<'_, 'de>) -> Result<V, ParseError>,
+    {
+        let start = self.input.offset(self.rest);
+        self.parse(context("table", tag("{")))?;
+        self.parser.budget.element()?;
+        self.parser.budget.enter()?;
+        let mut access = TableAccess {
+            de: self,
+            start,
+            first: true,
+            done: false,
+            index: 0,
+            key: None,
+            positional: false,
+            seen: Map::new(),
+            annotations: vec![],
+            sparse: None,
+        };
+        let result = visit(&mut access).and_then(|value| {
+            access.skip_rest()?;
+            Ok(value)
+        });
+        if result.is_ok() {
+            let annotations = std::mem::take(&mut access.annotations);
+            self.parser.note_index_comments(0, annotations);
+        }
+        self.parser.budget.leave();
+        result
+    }
+}
+
+/// Reads the entries of a table as either a sequence or a map. Positional entries are given their
+/// (1-based) Lua index as a key when read as a map.
+struct TableAccess<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    /// Offset of the table in the input.
+    start: usize,
+    first: bool,
+    done: bool,
+    /// Number of positional entries read so far.
+    index: i64,
+    /// Key of the entry currently being read, for error reporting.
+    key: Option<Key<'de>>,
+    /// Whether the entry currently being read is positional.
+    positional: bool,
+    /// Where each key read so far was found.
+    seen: Map<Key<'de>, &'de str>,
+    /// The index comment after each positional entry read so far, and the input after the entry.
+    annotations: Vec<(Option<i64>, &'de str)>,
+    /// The rest of a sequence that turned out to have keyed entries, laid out by index.
+    sparse: Option<std::vec::IntoIter<Value<'de>>>,
+}
+
+impl<'a, 'de> TableAccess<'a, 'de> {
+    /// Move to the next entry, returning `false` once the end of the table has been consumed.
+    fn has_next(&mut self) -> Result<bool, ParseError> {
+        if self.done {
+            return Ok(false);
+        }
+
+        if self.first {
+            self.first = false;
+        } else if !self.de.eat('}')? {
+            // entries are separated by `,` or `;`, and the last one may be followed by either
+            self.de.parse(context("end of table ('}')", one_of(",;")))?;
+        } else {
+            self.done = true;
+            return Ok(false);
+        }
+
+        self.done = self.de.eat('}')?;
+        Ok(!self.done)
+    }
+
+    /// Consume the entries that the visitor left unread.
+    fn skip_rest(&mut self) -> Result<(), ParseError> {
+        while self.has_next()? {
+            self.de.parse(|i| nom::combinator::opt(field_key)(i))?;
+            self.de.parse_counted(ValueParser::skip_value)?;
+        }
+        Ok(())
+    }
+
+    /// Note the index comment after the positional entry that was just read, if asked to.
+    fn note_index_comment(&mut self) {
+        if self.de.parser.options.check_index_comments {
+            let annotated = index_comment(self.de.rest).ok().map(|(_, ix)| ix);
+            self.annotations.push((annotated, self.de.rest));
+        }
+    }
+
+    /// Read the next keyed entry's key, deciding what to do if it was seen before. Returns `None`
+    /// if the entry is to be left out.
+    fn next_key(&mut self) -> Result<Option<Key<'de>>, ParseError> {
+        let position = self.de.rest;
+        let key = self.de.parse(field_key)?;
+        let Some(&first) = self.seen.get(&key) else {
+            self.seen.insert(key.clone(), position);
+            return Ok(Some(key));
+        };
+
+        let duplicate = Duplicate {
+            key,
+            first,
+            duplicate: position,
+        };
+        let policy = self.de.parser.options.duplicate_keys;
+        if policy == DuplicateKeys::KeepFirst {
+            self.de.parser.duplicates.borrow_mut().push(duplicate);
+            self.de.parse_counted(ValueParser::skip_value)?;
+            return Ok(None);
+        }
+
+        // the other entries have been visited already, so start over with this table built first
+        if policy != DuplicateKeys::Error {
+            self.de.rebuild.insert(self.start);
+            self.de.retry = true;
+        }
+        Err(ParseError::DuplicateKey(duplicate.warning(self.de.input)))
+    }
+
+    /// Read the rest of a table that is being read as a sequence all at once, after a keyed entry
+    /// turned up in it. WoW writes the entries of an array after a hole keyed by their index, and
+    /// those may come in any order.
+    fn read_sparse_rest(&mut self) -> Result<(), ParseError> {
+        let skipped = self.index as usize;
+        let rest = self
+            .de
+            .parse_counted(|parser, i| parser.table_entries(i, skipped))?;
+        let (array, named) = match rest {
+            Table::Empty => (vec![], Map::new()),
+            Table::Array(array) => (array, Map::new()),
+            Table::FloatArray(floats) => {
+                (floats.into_iter().map(Value::Float).collect(), Map::new())
+            }
+            Table::Named(named) => (vec![], named),
+            Table::MixedTable { array, named } => (array, named),
+        };
+
+        // the entries already read are positional, so they take precedence over any keys
+        let mut sequence = Vec::with_capacity(skipped + array.len());
+        sequence.resize_with(skipped, || Value::Nil);
+        sequence.extend(array);
+        let mut sequence = sequence_of(sequence, named)?;
+        sequence.drain(..skipped);
+
+        self.sparse = Some(sequence.into_iter());
+        self.done = true;
+        Ok(())
+    }
+}
+
+impl<'a, 'de> de::SeqAccess<'de> for TableAccess<'a, 'de> {
+    type Error = ParseError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if self.sparse.is_none() {
+            if !self.has_next()? {
+                return Ok(None);
+            }
+            if field_key(self.de.rest).is_ok() {
+                self.read_sparse_rest()?;
+            }
+        }
+        let value = match &mut self.sparse {
+            Some(values) => match values.next() {
+                Some(value) => Some(value),
+                None => return Ok(None),
+            },
+            None => None,
+        };
+
+        self.index += 1;
+        let result = match value {
+            Some(value) => seed.deserialize(ValueDeserializer(value)),
+            None => {
+                let result = seed.deserialize(&mut *self.de);
+                self.note_index_comment();
+                result
+            }
+        };
+        result
+            .map(Some)
+            .map_err(|err| err.within(Key::Int(self.index)))
+    }
+}
+
+impl<'a, 'de> de::MapAccess<'de> for TableAccess<'a, 'de> {
+    type Error = ParseError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        let key = loop {
+            if !self.has_next()? {
+                return Ok(None);
+            }
+            self.positional = field_key(self.de.rest).is_err();
+            if self.positional {
+                self.index += 1;
+                break Key::Int(self.index);
+            }
+            if let Some(key) = self.next_key()? {
+                break key;
+            }
+        };
+        self.key = Some(key.clone());
+
+        seed.deserialize(KeyDeserializer(key))
+            .map(Some)
+            .map_err(|err| err.within(self.key.take().unwrap()))
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| ParseError::SerdeCustom("value requested before key".into()))?;
+
+        let result = seed.deserialize(&mut *self.de);
+        if self.positional {
+            self.note_index_comment();
+        }
+        result.map_err(|err| err.within(key))
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = ParseError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        if !self.at_table()? {
+            return self.visit_scalar(visitor);
+        }
+
+        // a table with any keyed entry is a map, as in `ValueDeserializer`
+        let keyed = match self.first_entry()? {
+            FirstEntry::Empty => false,
+            FirstEntry::Keyed => true,
+            FirstEntry::Positional => self.has_keyed_entry()?,
+        };
+        if keyed {
+            self.deserialize_map(visitor)
+        } else {
+            self.visit_table(|access| visitor.visit_seq(access))
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.skip_spacing()?;
+        match nil(self.rest) {
+            Ok((rest, _)) => {
+                self.rest = rest;
+                visitor.visit_none()
+            }
+            Err(_) => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        if name == RAW_LUA_TOKEN {
+            self.skip_spacing()?;
+            let source = self.parse_counted(|parser, i| recognize(|i| parser.skip_value(i))(i))?;
+            return visitor.visit_borrowed_str(source);
+        }
+        // a `Value` is built in one go, since its tables may mix positional and keyed entries in
+        // any order
+        if name == VALUE_TOKEN {
+            self.skip_spacing()?;
+            let value = self.parse_counted(ValueParser::value)?;
+            return visitor.visit_newtype_struct(ValueDeserializer(value));
+        }
+
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        if !self.at_table()? {
+            return Err(ParseError::SerdeCustom(
+                "expected named or mixed table".into(),
+            ));
+        }
+
+        let start = self.input.offset(self.rest);
+        if self.rebuild.contains(&start) {
+            let table = self.parse_counted(ValueParser::value)?;
+            return ValueDeserializer(table).deserialize_map(visitor);
+        }
+
+        self.visit_table(|access| visitor.visit_map(access))
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        if !self.at_table()? {
+            return Err(ParseError::SerdeCustom("expected array table".into()));
+        }
+
+        // keyed entries may come in any order, so lay them out by index before visiting them
+        if self.first_entry()? == FirstEntry::Keyed {
+            let table = self.parse_counted(ValueParser::value)?;
+            return ValueDeserializer(table).deserialize_seq(visitor);
+        }
+
+        self.visit_table(|access| visitor.visit_seq(access))
+    }
+
+    /// Enums are externally tagged, as in `ValueDeserializer::deserialize_enum`.
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        if !self.at_table()? {
+            return match self.parse(context("enum variant name", string))? {
+                Value::String(variant) => visitor.visit_enum(variant.into_deserializer()),
+                _ => unreachable!("string only produces strings"),
+            };
+        }
+
+        self.visit_table(|access| {
+            let key = match access.has_next()? {
+                true => access.de.parse(field_key).ok(),
+                false => None,
+            };
+            let variant = match key {
+                Some(Key::String(variant)) => variant,
+                _ => {
+                    return Err(ParseError::SerdeCustom(
+                        "expected enum variant name or a table with a single key".into(),
+                    ))
+                }
+            };
+
+            let value = visitor.visit_enum(VariantAccess {
+                de: &mut *access.de,
+                key: Key::String(variant),
+            })?;
+            if access.has_next()? {
+                return Err(ParseError::SerdeCustom(
+                    "expected a table with a single key for enum variant".into(),
+                ));
+            }
+            Ok(value)
+        })
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.skip()?;
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct tuple
+        tuple_struct struct identifier
+    }
+}
+
+/// A non-unit enum variant, stored as `{ [variant] = value }`.
+struct VariantAccess<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    key: Key<'de>,
+}
+
+impl<'a, 'de> de::EnumAccess<'de> for VariantAccess<'a, 'de> {
+    type Error = ParseError;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(KeyDeserializer(self.key.clone()))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'a, 'de> de::VariantAccess<'de> for VariantAccess<'a, 'de> {
+    type Error = ParseError;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        self.de.skip_spacing()?;
+        match self
+            .de
+            .parse(alt((boolean, map(table_empty, Value::Table))))?
+        {
+            Value::Bool(true) | Value::Table(_) => Ok(()),
+            value => Err(ParseError::SerdeCustom(format!(
+                "expected unit variant, found {:?}",
+                value
+            ))
+            .within(self.key)),
+        }
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        let key = self.key;
+        seed.deserialize(self.de).map_err(|err| err.within(key))
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let key = self.key;
+        de::Deserializer::deserialize_seq(self.de, visitor).map_err(|err| err.within(key))
+    }
+
+    fn struct_variant<V>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let key = self.key;
+        de::Deserializer::deserialize_map(self.de, visitor).map_err(|err| err.within(key))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use pretty_assertions::assert_eq;
+    use serde::Deserialize;
+
+    use super::Deserializer;
+    use crate::{Limits, Value, ValueDeserializer};
+
+    #[test]
+    fn stream_nested_tables() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Test {
+            name: String,
+            values: Vec<f64>,
+            keyed: HashMap<u32, bool>,
+        }
+
+        let mut de = Deserializer::from_str(
+            r#"{
+                ["ignored"] = { 1, { 2, 3 }, "four" },
+                ["name"] = "x\ty", -- comment
+                ["values"] = {
+                    0.5, -- [1]
+                    1.5; -- [2]
+                },
+                ["keyed"] = { [3] = true, [5] = false },
+            } "#,
+        );
+        let test = Test::deserialize(&mut de).unwrap();
+        de.end().unwrap();
+
+        assert_eq!(
+            test,
+            Test {
+                name: "x\ty".to_string(),
+                values: vec![0.5, 1.5],
+                keyed: HashMap::from([(3, true), (5, false)]),
+            }
+        );
+    }
+
+    #[test]
+    fn stream_skips_unread_entries() {
+        let mut de = Deserializer::from_str("{ 1, 'two', { 3 } } ");
+        let first: (u32,) = Deserialize::deserialize(&mut de).unwrap();
+        de.end().unwrap();
+
+        assert_eq!(first, (1,));
+    }
+
+    #[test]
+    fn stream_leaves_failed_tables() {
+        let mut de = Deserializer::from_str("{ { 1 }, { 'two' } }");
+        assert!(Vec::<Vec<u32>>::deserialize(&mut de).is_err());
+        assert_eq!(de.parser.budget.depth_left(), Limits::default().max_depth);
+    }
+
+    #[test]
+    fn stream_mixed_table_as_map() {
+        #[derive(Deserialize, PartialEq, Eq, Hash, Debug)]
+        #[serde(untagged)]
+        enum Key {
+            Index(u32),
+            Name(String),
+        }
+
+        let mut de = Deserializer::from_str("{ 5, foo = 7, 6 }");
+        let result: HashMap<Key, u32> = Deserialize::deserialize(&mut de).unwrap();
+
+        assert_eq!(
+            result,
+            HashMap::from([
+                (Key::Index(1), 5),
+                (Key::Index(2), 6),
+                (Key::Name("foo".to_string()), 7)
+            ])
+        );
+    }
+
+    #[test]
+    fn stream_any_table_as_value_does() {
+        #[derive(Deserialize, PartialEq, Eq, Hash, Debug)]
+        #[serde(untagged)]
+        enum Key {
+            Index(u32),
+            Name(String),
+        }
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        #[serde(untagged)]
+        enum Any {
+            Int(i64),
+            Str(String),
+            Seq(Vec<Any>),
+            Map(HashMap<Key, Any>),
+        }
+
+        let read = |input| {
+            let streamed = Any::deserialize(&mut Deserializer::from_str(input)).unwrap();
+            let value = crate::from_str::<Value>(input).unwrap();
+            assert_eq!(
+                streamed,
+                Any::deserialize(ValueDeserializer(value)).unwrap()
+            );
+            streamed
+        };
+
+        assert!(matches!(read("{ 'a', ['k'] = 1 }"), Any::Map(_)));
+        assert!(matches!(read("{ 'a', [2] = 'b' }"), Any::Map(_)));
+        assert!(matches!(read("{ 'a', { 1 }, 'b' }"), Any::Seq(_)));
+        assert!(matches!(
+            read("{ { 1, { 2, k = 3 } }, { 4 }, { 5, [2] = 6 } }"),
+            Any::Seq(_)
+        ));
+    }
+
+    #[test]
+    fn stream_keyed_table_as_seq() {
+        let mut de = Deserializer::from_str("{ 1, foo = 2 }");
+        assert!(Vec::<u32>::deserialize(&mut de).is_err());
+    }
+
+    #[test]
+    fn stream_sparse_seq() {
+        let mut de = Deserializer::from_str("{ 1, 2, [5] = 5, [3] = 3 }");
+        let result: Vec<Option<u32>> = Deserialize::deserialize(&mut de).unwrap();
+        de.end().unwrap();
+        assert_eq!(result, vec![Some(1), Some(2), Some(3), None, Some(5)]);
+
+        let mut de = Deserializer::from_str("{ [2] = 4, [1] = 3 }");
+        let result: Vec<u32> = Deserialize::deserialize(&mut de).unwrap();
+        de.end().unwrap();
+        assert_eq!(result, vec![3, 4]);
+    }
+
+    #[test]
+    fn stream_enum() {
+        #[derive(Deserialize, Debug, PartialEq, Eq, Hash)]
+        #[serde(rename_all = "lowercase")]
+        enum Kind {
+            Manual,
+            Raid { id: u32 },
+            Pair(u32, u32),
+        }
+
+        let mut de = Deserializer::from_str(
+            r#"{ "manual", { raid = { id = 7, }, }, { ["pair"] = { 1, 2 } }, { manual = {} } }"#,
+        );
+        let result: Vec<Kind> = Deserialize::deserialize(&mut de).unwrap();
+        assert_eq!(
+            result,
+            vec![
+                Kind::Manual,
+                Kind::Raid { id: 7 },
+                Kind::Pair(1, 2),
+                Kind::Manual
+            ]
+        );
+
+        let mut de = Deserializer::from_str(r#"{ ["manual"] = 3 }"#);
+        let result: HashMap<Kind, u32> = Deserialize::deserialize(&mut de).unwrap();
+        assert_eq!(result, HashMap::from([(Kind::Manual, 3)]));
+
+        let mut de = Deserializer::from_str("{ manual = true, raid = { id = 1 } }");
+        assert!(Kind::deserialize(&mut de).is_err());
+    }
+}
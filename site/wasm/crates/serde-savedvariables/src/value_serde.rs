@@ -0,0 +1,247 @@
+//! `Serialize` and `Deserialize` for `Value`, so that it can be used with any serde data format.
+
+use std::{
+    borrow::Cow,
+    fmt::{self, Formatter},
+};
+
+use serde::{
+    de::{self, MapAccess, SeqAccess, Visitor},
+    ser::{SerializeMap, SerializeSeq},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{Key, Map, Table, Value};
+
+/// Tables are serialized as sequences if they only have positional entries, and as maps otherwise.
+/// Keys keep their type, so formats that only allow string keys (such as JSON) reject tables with
+/// boolean or float keys.
+impl<'a> Serialize for Value<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Nil => serializer.serialize_unit(),
+            Value::Bool(b) => serializer.serialize_bool(*b),
+            Value::Int(i) => serializer.serialize_i64(*i),
+            Value::BigInt(i) => match u64::try_from(*i) {
+                Ok(i) => serializer.serialize_u64(i),
+                Err(_) => serializer.serialize_i128(*i),
+            },
+            Value::Float(f) => serializer.serialize_f64(*f),
+            Value::String(s) => serializer.serialize_str(s),
+            Value::Table(table) => table.serialize(serializer),
+            Value::Duplicates(values) => values.serialize(serializer),
+        }
+    }
+}
+
+impl<'a> Serialize for Table<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Table::Empty | Table::Array(_) | Table::FloatArray(_) => {
+                let mut seq = serializer.serialize_seq(Some(self.len()))?;
+                for (_, value) in self.iter() {
+                    seq.serialize_element(&*value)?;
+                }
+                seq.end()
+            }
+            Table::Named(_) | Table::MixedTable { .. } => {
+                let mut map = serializer.serialize_map(Some(self.len()))?;
+                for (key, value) in self.iter() {
+                    map.serialize_entry(&key, &*value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+impl<'a> Serialize for Key<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Key::Bool(b) => serializer.serialize_bool(*b),
+            Key::Int(i) => serializer.serialize_i64(*i),
+            Key::Float(f) => serializer.serialize_f64(*f),
+            Key::String(s) => serializer.serialize_str(s),
+        }
+    }
+}
+
+/// Name used to recognize `Value` in `Deserializer::deserialize_newtype_struct`, which builds it
+/// directly rather than going through `deserialize_any`.
+pub(crate) const VALUE_TOKEN: &str = "$serde_savedvariables::Value";
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value<'de>;
+
+    fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+        formatter.write_str("a value that can be stored in a Lua table")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Self::Value, E> {
+        Ok(Value::Bool(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(Value::Int(v))
+    }
+
+    fn visit_i128<E: de::Error>(self, v: i128) -> Result<Self::Value, E> {
+        Ok(i64::try_from(v).map_or(Value::BigInt(v), Value::Int))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        self.visit_i128(v as i128)
+    }
+
+    fn visit_u128<E: de::Error>(self, v: u128) -> Result<Self::Value, E> {
+        match i128::try_from(v) {
+            Ok(v) => self.visit_i128(v),
+            Err(_) => Ok(Value::Float(v as f64)),
+        }
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        Ok(Value::Float(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(Value::String(Cow::Owned(v.to_owned())))
+    }
+
+    fn visit_borrowed_str<E: de::Error>(self, v: &'de str) -> Result<Self::Value, E> {
+        Ok(Value::String(Cow::Borrowed(v)))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Self::Value, E> {
+        Ok(Value::String(Cow::Owned(v)))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(Value::Nil)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(Value::Nil)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        Deserialize::deserialize(deserializer)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut array = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(value) = seq.next_element()? {
+            array.push(value);
+        }
+        Ok(Value::Table(if array.is_empty() {
+            Table::Empty
+        } else {
+            Table::Array(array)
+        }))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+        let mut named = Map::default();
+        while let Some((key, value)) = access.next_entry::<Key, Value>()? {
+            // Lua doesn't store keys assigned `nil`
+            if !value.is_nil() {
+                named.insert(key, value);
+            }
+        }
+        Ok(Value::Table(if named.is_empty() {
+            Table::Empty
+        } else {
+            Table::Named(named)
+        }))
+    }
+}
+
+/// Other formats see this as a newtype struct, and unwrap it as usual.
+impl<'de: 'a, 'a> Deserialize<'de> for Value<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_newtype_struct(VALUE_TOKEN, ValueVisitor)
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for Key<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = deserializer.deserialize_any(ValueVisitor)?;
+        Key::try_from(value).map_err(|value| {
+            de::Error::custom(format!("{:?} cannot be used as a table key", value))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::borrow::Cow;
+
+    use pretty_assertions::assert_eq;
+    use serde::Deserialize;
+
+    use crate::{from_str, Key, Map, Table, Value};
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Recording<'a> {
+        encounter: String,
+        #[serde(borrow)]
+        extra: Value<'a>,
+    }
+
+    #[test]
+    fn value_in_struct() {
+        let input = r#"{ ["encounter"] = "raid", ["extra"] = { 1, "two", ["three"] = 3.5 } }"#;
+        let recording: Recording = from_str(input).unwrap();
+
+        assert_eq!(recording.encounter, "raid");
+        assert_eq!(recording.extra[1], Value::Int(1));
+        assert_eq!(recording.extra[2].as_str(), Some("two"));
+        assert_eq!(recording.extra["three"], Value::Float(3.5));
+        assert_eq!(recording.extra.as_table().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn value_json_round_trip() {
+        let value = Value::Table(Table::Named(Map::from([
+            (
+                Key::String(Cow::Borrowed("list")),
+                Value::Table(Table::Array(vec![
+                    Value::Int(1),
+                    Value::BigInt(1 << 63),
+                    Value::Bool(false),
+                ])),
+            ),
+            (
+                Key::String(Cow::Borrowed("name")),
+                Value::String(Cow::Borrowed("raid")),
+            ),
+            (
+                Key::String(Cow::Borrowed("none")),
+                Value::Table(Table::Empty),
+            ),
+        ])));
+
+        let json = serde_json::to_value(&value).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "list": [1, 1u64 << 63, false], "name": "raid", "none": [] })
+        );
+        let text = json.to_string();
+        assert_eq!(serde_json::from_str::<Value>(&text).unwrap(), value);
+
+        let mixed: Value = from_str(r#"{ "a", ["b"] = 2 }"#).unwrap();
+        assert_eq!(
+            serde_json::to_value(&mixed).unwrap(),
+            serde_json::json!({ "1": "a", "b": 2 })
+        );
+    }
+}
@@ -2,12 +2,11 @@ use std::{cell::RefCell, rc::Rc};
 
 use ouroboros::self_referencing;
 use parser::{ParsedRecording, Recording, SavedVariablesError};
+use serde_savedvariables::ChunkedArray;
 
 use serde::Serialize;
 use wasm_bindgen::{prelude::wasm_bindgen, JsValue};
 
-use crate::parser::RecordingData;
-
 mod parser;
 
 #[self_referencing]
@@ -20,7 +19,18 @@ struct SavedVariablesRefInner {
 
 #[wasm_bindgen]
 pub struct SavedVariablesRef {
-    inner: Rc<SavedVariablesRefInner>,
+    /// The text that the recordings borrow from. A file that was read all at once is a single
+    /// source, while a streamed one has a source per recording.
+    sources: Vec<Rc<SavedVariablesRefInner>>,
+    skipped: Vec<SkippedRange>,
+}
+
+/// A part of the file that couldn't be read, and was skipped to keep the rest.
+#[derive(Serialize, Clone)]
+struct SkippedRange {
+    start: usize,
+    end: usize,
+    reason: String,
 }
 
 #[wasm_bindgen(skip_typescript)]
@@ -36,12 +46,23 @@ pub struct RecordingRef {
 #[wasm_bindgen]
 impl SavedVariablesRef {
     pub fn length(&self) -> usize {
-        self.inner.borrow_data().len()
+        self.sources
+            .iter()
+            .map(|source| source.borrow_data().len())
+            .sum()
     }
 
-    pub fn get(&self, index: usize) -> Option<RecordingRef> {
+    pub fn get(&self, mut index: usize) -> Option<RecordingRef> {
+        let source = self.sources.iter().find(|source| {
+            let len = source.borrow_data().len();
+            if index < len {
+                return true;
+            }
+            index -= len;
+            false
+        })?;
         let builder = RecordingRefTryBuilder {
-            source: self.inner.clone(),
+            source: source.clone(),
             cached_data: RefCell::new(None),
             data_builder: |source| {
                 source
@@ -54,20 +75,18 @@ impl SavedVariablesRef {
 
         builder.try_build().ok()
     }
+
+    /// The parts of the file that were skipped because they couldn't be read, as a list of
+    /// `{ start, end, reason }` with byte offsets into the file.
+    pub fn skipped(&self) -> JsValue {
+        serde_wasm_bindgen::to_value(&self.skipped).expect("serialization to always succeed")
+    }
 }
 
 impl RecordingRef {
     /// This runs the data parsing without hitting `serde_wasm_bindgen`. used for testing
     pub fn test_parse_data(&self) -> Result<ParsedRecording<'_>, String> {
-        let data = self.borrow_data();
-
-        match data.data {
-            RecordingData::Parsed(_) => Err("found parsed data".to_string()),
-            RecordingData::Unparsed(ref raw) => {
-                let data = parser::parse_compressed_recording(raw).map_err(|e| e.to_string())?;
-                Ok(data)
-            }
-        }
+        parser::parse_recording_data(&self.borrow_data().data).map_err(|e| e.to_string())
     }
 
     fn serializer() -> serde_wasm_bindgen::Serializer {
@@ -91,38 +110,48 @@ impl RecordingRef {
 
     #[wasm_bindgen(getter, skip_typescript)]
     pub fn data(&self) -> Result<JsValue, String> {
-        let data = self.borrow_data();
-
-        match data.data {
-            RecordingData::Parsed(ref data) => {
-                let value = data
-                    .serialize(&RecordingRef::serializer())
-                    .map_err(|e| e.to_string())?;
-                Ok(value)
-            }
-            RecordingData::Unparsed(ref raw) => {
-                if self.borrow_cached_data().borrow().is_some() {
-                    Ok(self.borrow_cached_data().borrow().clone().unwrap())
-                } else {
-                    let data =
-                        parser::parse_compressed_recording(raw).map_err(|e| e.to_string())?;
-                    let value = data
-                        .serialize(&RecordingRef::serializer())
-                        .map_err(|e| e.to_string())?;
-                    *(self.borrow_cached_data().borrow_mut()) = Some(value.clone());
-                    Ok(value)
-                }
-            }
+        if let Some(value) = self.borrow_cached_data().borrow().as_ref() {
+            return Ok(value.clone());
         }
+
+        let data =
+            parser::parse_recording_data(&self.borrow_data().data).map_err(|e| e.to_string())?;
+        let value = data
+            .serialize(&RecordingRef::serializer())
+            .map_err(|e| e.to_string())?;
+        *(self.borrow_cached_data().borrow_mut()) = Some(value.clone());
+        Ok(value)
     }
 }
 
 #[wasm_bindgen]
 pub fn parse_saved_variables(blob: String) -> Result<SavedVariablesRef, JsValue> {
+    parse_saved_variables_string(blob)
+}
+
+/// Parse a SavedVariables file as read from disk. Any bytes that aren't valid UTF-8 are replaced
+/// in the strings they appear in, instead of failing the whole file.
+#[wasm_bindgen]
+pub fn parse_saved_variables_bytes(blob: &[u8]) -> Result<SavedVariablesRef, JsValue> {
+    let text = serde_savedvariables::decode_lossy(blob).map_err(|e| format!("{}", e))?;
+    parse_saved_variables_string(text.into_owned())
+}
+
+fn parse_saved_variables_string(blob: String) -> Result<SavedVariablesRef, JsValue> {
+    let mut skipped = vec![];
     let result = SavedVariablesRefInnerTryBuilder {
         source: blob,
         data_builder: |source| {
-            Ok(parser::parse_saved_variables(source)?
+            let (saved_variables, skipped_parts) = parser::recover_saved_variables(source)?;
+            skipped = skipped_parts
+                .into_iter()
+                .map(|part| SkippedRange {
+                    start: part.range.start,
+                    end: part.range.end,
+                    reason: part.error.to_string(),
+                })
+                .collect();
+            Ok(saved_variables
                 .recordings
                 .into_iter()
                 .map(Rc::new)
@@ -136,7 +165,83 @@ pub fn parse_saved_variables(blob: String) -> Result<SavedVariablesRef, JsValue>
             .map_err(|e: SavedVariablesError| format!("{}", e))?,
     );
 
-    Ok(SavedVariablesRef { inner: data })
+    Ok(SavedVariablesRef {
+        sources: vec![data],
+        skipped,
+    })
+}
+
+/// Parses a SavedVariables file as it is read, so that recordings are available before the whole
+/// file has arrived.
+#[wasm_bindgen]
+pub struct SavedVariablesStream {
+    recordings: ChunkedArray,
+    sources: Vec<Rc<SavedVariablesRefInner>>,
+    skipped: Vec<SkippedRange>,
+}
+
+impl Default for SavedVariablesStream {
+    fn default() -> Self {
+        SavedVariablesStream {
+            recordings: parser::recordings_stream(),
+            sources: vec![],
+            skipped: vec![],
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl SavedVariablesStream {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> SavedVariablesStream {
+        SavedVariablesStream::default()
+    }
+
+    /// Add the next chunk of the file. Returns how many recordings it completed. Recordings that
+    /// can't be read are skipped, as with `parse_saved_variables`.
+    pub fn push(&mut self, chunk: &[u8]) -> Result<usize, JsValue> {
+        let elements = self
+            .recordings
+            .feed(chunk)
+            .map_err(|e| format!("{}", SavedVariablesError::from(e)))?;
+        let count = self.sources.len();
+        for element in elements {
+            let start = element.offset;
+            let end = element.offset + element.text.len();
+            let result = SavedVariablesRefInnerTryBuilder {
+                source: element.text,
+                data_builder: |source| {
+                    parser::parse_recording(source).map(|recording| vec![Rc::new(recording)])
+                },
+            }
+            .try_build();
+            match result {
+                Ok(source) => self.sources.push(Rc::new(source)),
+                Err(err) => self.skipped.push(SkippedRange {
+                    start,
+                    end,
+                    reason: err.to_string(),
+                }),
+            }
+        }
+        Ok(self.sources.len() - count)
+    }
+
+    /// The recordings read so far.
+    pub fn snapshot(&self) -> SavedVariablesRef {
+        SavedVariablesRef {
+            sources: self.sources.clone(),
+            skipped: self.skipped.clone(),
+        }
+    }
+
+    /// Signal the end of the file, checking that it wasn't cut off.
+    pub fn finish(&mut self) -> Result<SavedVariablesRef, JsValue> {
+        self.recordings
+            .finish()
+            .map_err(|e| format!("{}", SavedVariablesError::from(e)))?;
+        Ok(self.snapshot())
+    }
 }
 
 #[wasm_bindgen]
@@ -15,12 +15,62 @@ pub enum SavedVariablesError {
     SignCastError(#[from] TryFromIntError),
 }
 
+/// The global that the addon stores its data in. Must match `## SavedVariables` in the TOC.
+const STORAGE_GLOBAL: &str = "Profiling2_Storage";
+
 pub fn parse_saved_variables(data: &str) -> Result<SavedVariables<'_>, SavedVariablesError> {
+    Ok(serde_savedvariables::global_from_str(data, STORAGE_GLOBAL)?)
+}
+
+/// Find the recordings in a SavedVariables file that is read a chunk at a time. Each one can be
+/// parsed with [`parse_recording`].
+pub fn recordings_stream() -> serde_savedvariables::ChunkedArray {
+    serde_savedvariables::ChunkedArray::new(&[STORAGE_GLOBAL, "recordings"])
+}
+
+pub fn parse_recording(data: &str) -> Result<Recording<'_>, SavedVariablesError> {
     Ok(serde_savedvariables::from_str(data)?)
 }
 
-pub fn parse_compressed_recording(data: &str) -> Result<ParsedRecording<'_>, SavedVariablesError> {
-    Ok(serde_libserialize::from_str(data)?)
+/// Parse a SavedVariables file, keeping whichever recordings can be read if it is broken, e.g.
+/// because WoW crashed while writing it. Also returns the parts of the file that were skipped.
+pub fn recover_saved_variables(
+    data: &str,
+) -> Result<(SavedVariables<'_>, Vec<serde_savedvariables::Skipped>), SavedVariablesError> {
+    let err = match parse_saved_variables(data) {
+        Ok(saved_variables) => return Ok((saved_variables, vec![])),
+        Err(err) => err,
+    };
+
+    let recovered =
+        serde_savedvariables::recover_array_from_str(data, &[STORAGE_GLOBAL, "recordings"])
+            .map_err(|_| err)?;
+    Ok((
+        SavedVariables {
+            recordings: recovered.values,
+        },
+        recovered.skipped,
+    ))
+}
+
+pub fn parse_compressed_recording(
+    data: &str,
+) -> Result<ParsedRecording<'static>, SavedVariablesError> {
+    let max_bytes = serde_libserialize::Limits::default().max_allocation;
+    let decompressed = serde_libserialize::deflate::decompress_with_limit(data, max_bytes)
+        .map_err(serde_libserialize::DeserializationError::from)?;
+    let recording: ParsedRecording = serde_libserialize::from_bytes(&decompressed)?;
+    Ok(recording.into_owned())
+}
+
+/// Parse the data of a recording, whichever format it was saved in.
+pub fn parse_recording_data<'a>(
+    data: &'a RecordingData,
+) -> Result<ParsedRecording<'a>, SavedVariablesError> {
+    match data {
+        RecordingData::Unparsed(raw) => parse_compressed_recording(raw),
+        RecordingData::Table(raw) => Ok(raw.parse()?),
+    }
 }
 
 #[cfg(test)]
@@ -44,6 +94,69 @@ mod test {
         };
     }
 
+    #[test]
+    fn parse_extra_globals() {
+        let result = super::parse_saved_variables(
+            r#"Profiling2_Other = { 1, 2, 3 }
+            Profiling2_Storage = {
+                ["recordings"] = {}
+              }
+            Profiling2_Char = "foo""#,
+        );
+
+        assert_eq!(result.unwrap(), SavedVariables { recordings: vec![] });
+    }
+
+    #[test]
+    fn parse_missing_storage() {
+        let result = super::parse_saved_variables(r#"Profiling_Storage = { ["recordings"] = {} }"#);
+
+        assert!(matches!(
+            result,
+            Err(SavedVariablesError::ParseError(
+                serde_savedvariables::ParseError::MissingGlobal(_)
+            ))
+        ));
+    }
+
+    #[test]
+    fn parse_syntax_error() {
+        let result = super::parse_saved_variables(
+            r#"Profiling2_Storage = {
+                ["recordings"] = { 1 2 }
+              }"#,
+        );
+
+        match result {
+            Err(SavedVariablesError::ParseError(
+                serde_savedvariables::ParseError::SyntaxError(err),
+            )) => assert_eq!((err.line, err.column), (2, 38)),
+            other => panic!("expected a syntax error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_too_deep() {
+        let nested = format!("{}{}", "{".repeat(10_000), "}".repeat(10_000));
+        let data = format!(
+            "Profiling2_Storage = {{ [\"recordings\"] = {{ {} }} }}",
+            nested
+        );
+
+        assert!(matches!(
+            super::parse_saved_variables(&data),
+            Err(SavedVariablesError::ParseError(
+                serde_savedvariables::ParseError::LimitExceeded(_)
+            ))
+        ));
+        let (result, skipped) = super::recover_saved_variables(&data).unwrap();
+        assert!(result.recordings.is_empty());
+        assert!(matches!(
+            skipped[0].error,
+            serde_savedvariables::ParseError::LimitExceeded(_)
+        ));
+    }
+
     #[test]
     fn parse_apr22_data() {
         let result =
@@ -56,6 +169,7 @@ mod test {
                 data: RecordingData::Unparsed(data),
                 ..
             }) => {
+                assert!(matches!(data, std::borrow::Cow::Borrowed(_)));
                 assert_eq!(data.len(), 9872);
                 let decoded = serde_libserialize::deflate::decode_for_print(data)
                     .expect("to decode successfully");
@@ -67,16 +181,55 @@ mod test {
             _ => assert!(false),
         };
 
+        assert!(matches!(result.recordings[0].data, RecordingData::Table(_)));
+        let parsed = parse_recording_data(&result.recordings[0].data).expect("to succeed");
+        assert!(parsed
+            .scripts
+            .keys()
+            .all(|key| matches!(key, std::borrow::Cow::Borrowed(_))));
         for recording in &mut result.recordings {
-            match &recording.data {
-                crate::parser::RecordingData::Unparsed(raw) => {
-                    parse_compressed_recording(raw).expect("to succeed");
-                }
-                _ => {}
-            }
+            parse_recording_data(&recording.data).expect("to succeed");
         }
     }
 
+    #[test]
+    fn recover_truncated_data() {
+        let data = include_str!("../../../test-data/test_apr24_2023.lua");
+        let (complete, skipped) = super::recover_saved_variables(data).unwrap();
+        assert!(skipped.is_empty());
+
+        // cut off in the middle of the last recording
+        let truncated = &data[..data.rfind("[\"encounter\"]").unwrap()];
+        assert!(super::parse_saved_variables(truncated).is_err());
+        let (result, skipped) = super::recover_saved_variables(truncated).unwrap();
+        assert_eq!(result.recordings.len(), complete.recordings.len() - 1);
+        assert_eq!(
+            result.recordings[..],
+            complete.recordings[..result.recordings.len()]
+        );
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].range.end, truncated.len());
+    }
+
+    #[test]
+    fn stream_data() {
+        let data = include_str!("../../../test-data/test_apr24_2023.lua");
+        let complete = super::parse_saved_variables(data).unwrap();
+
+        let mut stream = super::recordings_stream();
+        let mut elements = vec![];
+        for chunk in data.as_bytes().chunks(4096) {
+            elements.extend(stream.feed(chunk).unwrap());
+        }
+        stream.finish().unwrap();
+
+        let recordings = elements
+            .iter()
+            .map(|element| super::parse_recording(&element.text).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(recordings, complete.recordings);
+    }
+
     #[test]
     fn parse_apr24_data() {
         let result =
@@ -117,6 +270,96 @@ mod test {
         }
     }
 
+    #[test]
+    fn parse_sparse_and_dense_bins() {
+        let result =
+            super::parse_saved_variables(include_str!("../../../test-data/test_heiji_may30.lua"))
+                .unwrap();
+        let bins = |ix: usize| match &result.recordings[ix].data {
+            crate::parser::RecordingData::Unparsed(raw) => {
+                match parse_compressed_recording(raw).unwrap().on_update_delay {
+                    TrackerData::NewStyle { sketch, .. } => sketch.bins.unwrap(),
+                    other => panic!("expected a sketch, got {:?}", other),
+                }
+            }
+            _ => panic!("expected compressed data"),
+        };
+        let expected = |len: usize, counts: &[(usize, f64)]| {
+            let mut bins = vec![0.0; len];
+            for &(bin, count) in counts {
+                bins[bin - 1] = count;
+            }
+            bins
+        };
+
+        // written as a table keyed by bin, with holes at 57 and 58
+        assert_eq!(
+            bins(7),
+            expected(
+                60,
+                &[
+                    (7, 2.0),
+                    (13, 339.0),
+                    (17, 1020.0),
+                    (20, 766.0),
+                    (23, 1391.0),
+                    (24, 16238.0),
+                    (26, 31191.0),
+                    (27, 30353.0),
+                    (28, 24659.0),
+                    (30, 33230.0),
+                    (31, 10125.0),
+                    (32, 6282.0),
+                    (33, 3838.0),
+                    (34, 4037.0),
+                    (35, 2141.0),
+                    (36, 1221.0),
+                    (37, 996.0),
+                    (38, 756.0),
+                    (39, 601.0),
+                    (40, 251.0),
+                    (41, 2482.0),
+                    (42, 1245.0),
+                    (43, 16.0),
+                    (44, 32.0),
+                    (45, 13.0),
+                    (46, 9.0),
+                    (47, 8.0),
+                    (48, 1.0),
+                    (49, 6.0),
+                    (50, 7.0),
+                    (51, 2.0),
+                    (52, 1.0),
+                    (53, 4.0),
+                    (54, 2.0),
+                    (55, 4.0),
+                    (56, 2.0),
+                    (59, 2.0),
+                    (60, 1.0),
+                ]
+            )
+        );
+
+        // written as an array
+        assert_eq!(
+            bins(12),
+            expected(
+                53,
+                &[
+                    (27, 35.0),
+                    (28, 71.0),
+                    (30, 222.0),
+                    (31, 117.0),
+                    (32, 83.0),
+                    (33, 68.0),
+                    (34, 71.0),
+                    (35, 34.0),
+                    (36, 9.0),
+                ]
+            )
+        );
+    }
+
     #[test]
     fn parse_sokchoy_empty_data() {
         let result =
@@ -1,26 +1,127 @@
 use std::{borrow::Cow, collections::HashMap};
 
 use schemars::JsonSchema;
-use serde::{Deserialize, Deserializer, Serialize};
+use serde::{
+    de::{self, IgnoredAny, MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use serde_savedvariables::{RawLua, Value};
 
-#[derive(Debug, PartialEq, Deserialize, JsonSchema)]
+#[derive(Debug, PartialEq, JsonSchema)]
 #[serde(untagged)]
 pub enum RecordingData<'a> {
+    /// A recording compressed with LibSerialize + LibDeflate.
     #[schemars(skip)]
     Unparsed(Cow<'a, str>),
-    Parsed(ParsedRecording<'a>),
+    /// An uncompressed recording. It is only parsed once it is actually viewed.
+    Table(#[schemars(with = "ParsedRecording")] RawLua<'a>),
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for RecordingData<'a> {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        let raw = RawLua::deserialize(de)?;
+        if raw.is_table() {
+            Ok(RecordingData::Table(raw))
+        } else {
+            // read as a `Value` so that the string is borrowed if it has no escapes
+            match raw.to_value().map_err(serde::de::Error::custom)? {
+                Value::String(s) => Ok(RecordingData::Unparsed(s)),
+                value => Err(serde::de::Error::custom(format!(
+                    "expected compressed recording string, found {:?}",
+                    value
+                ))),
+            }
+        }
+    }
+}
+
+/// A string map key that borrows from the input where possible. serde only borrows a `Cow` when it
+/// is a field itself, so keys of a `HashMap<Cow<str>, _>` would otherwise always be copied.
+#[derive(PartialEq, Eq, Hash)]
+struct BorrowedKey<'a>(Cow<'a, str>);
+
+impl<'de: 'a, 'a> Deserialize<'de> for BorrowedKey<'a> {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        struct KeyVisitor;
+
+        impl<'de> Visitor<'de> for KeyVisitor {
+            type Value = BorrowedKey<'de>;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a string")
+            }
+
+            fn visit_borrowed_str<E>(self, v: &'de str) -> Result<Self::Value, E> {
+                Ok(BorrowedKey(Cow::Borrowed(v)))
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(BorrowedKey(Cow::Owned(v.to_owned())))
+            }
+
+            fn visit_string<E>(self, v: String) -> Result<Self::Value, E> {
+                Ok(BorrowedKey(Cow::Owned(v)))
+            }
+        }
+
+        de.deserialize_str(KeyVisitor)
+    }
+}
+
+fn deserialize_borrowed_keys<'de: 'a, 'a, D, V>(de: D) -> Result<HashMap<Cow<'a, str>, V>, D::Error>
+where
+    D: Deserializer<'de>,
+    V: Deserialize<'de>,
+{
+    let map = HashMap::<BorrowedKey<'a>, V>::deserialize(de)?;
+    Ok(map.into_iter().map(|(key, value)| (key.0, value)).collect())
+}
+
+fn deserialize_optional_borrowed_keys<'de: 'a, 'a, D, V>(
+    de: D,
+) -> Result<Option<HashMap<Cow<'a, str>, V>>, D::Error>
+where
+    D: Deserializer<'de>,
+    V: Deserialize<'de>,
+{
+    let map = Option::<HashMap<BorrowedKey<'a>, V>>::deserialize(de)?;
+    Ok(map.map(|map| map.into_iter().map(|(key, value)| (key.0, value)).collect()))
+}
+
+fn own_keys<V>(map: HashMap<Cow<'_, str>, V>) -> HashMap<Cow<'static, str>, V> {
+    map.into_iter()
+        .map(|(key, value)| (Cow::Owned(key.into_owned()), value))
+        .collect()
 }
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema)]
 #[schemars(deny_unknown_fields)]
 pub struct ParsedRecording<'a> {
+    #[serde(borrow, deserialize_with = "deserialize_borrowed_keys")]
     pub scripts: HashMap<Cow<'a, str>, TrackerData>,
+    #[serde(
+        borrow,
+        default,
+        deserialize_with = "deserialize_optional_borrowed_keys"
+    )]
     pub externals: Option<HashMap<Cow<'a, str>, TrackerData>>,
     #[serde(rename = "onUpdateDelay")]
     pub on_update_delay: TrackerData,
     pub sketch_params: Option<SketchParams>,
 }
 
+impl<'a> ParsedRecording<'a> {
+    /// Detach the recording from the input it was parsed from.
+    pub fn into_owned(self) -> ParsedRecording<'static> {
+        ParsedRecording {
+            scripts: own_keys(self.scripts),
+            externals: self.externals.map(own_keys),
+            on_update_delay: self.on_update_delay,
+            sketch_params: self.sketch_params,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema)]
 #[schemars(deny_unknown_fields)]
 pub struct SketchParams {
@@ -69,45 +170,14 @@ pub struct TrackerCore {
     pub total_time: f64,
 }
 
-#[derive(Deserialize, Eq, Hash, PartialEq)]
-#[serde(untagged)]
-enum MixedBinKey {
-    Integer(u64),
-    String(String),
-}
-
-#[derive(Deserialize)]
-#[serde(untagged)]
-enum MixedBinType {
-    Array(Vec<f64>),
-    Mixed(HashMap<MixedBinKey, f64>),
-}
-
-// TODO this should probably have a custom visitor for efficiency, but its such a rare case that we
-// take the easy way out.
+/// Bins are an array table when dense and a table keyed by bin index when sparse. Either way, empty
+/// bins are left as holes, and bin `i` (1-based, as the addon numbers them) ends up at index
+/// `i - 1`.
 fn deserialize_bins<'de, D: Deserializer<'de>>(de: D) -> Result<Option<Vec<f64>>, D::Error> {
-    match MixedBinType::deserialize(de)? {
-        MixedBinType::Array(vec) => Ok(Some(vec)),
-        MixedBinType::Mixed(map) => {
-            let mut result = vec![];
-
-            for (ix, value) in map.into_iter() {
-                let ix = match ix {
-                    MixedBinKey::Integer(ix) => ix as usize,
-                    MixedBinKey::String(s) => {
-                        s.parse::<usize>().map_err(serde::de::Error::custom)?
-                    }
-                };
-
-                if ix >= result.len() {
-                    result.resize(ix + 1, 0.0);
-                }
-                result[ix] = value;
-            }
-
-            Ok(Some(result))
-        }
-    }
+    let bins = Vec::<Option<f64>>::deserialize(de)?;
+    Ok(Some(
+        bins.into_iter().map(|bin| bin.unwrap_or(0.0)).collect(),
+    ))
 }
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema)]
@@ -121,7 +191,7 @@ pub struct SketchStats {
     pub trivial_count: u64,
 }
 
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema)]
+#[derive(Debug, PartialEq, Clone, Serialize, JsonSchema)]
 #[serde(untagged)]
 #[schemars(deny_unknown_fields)]
 pub enum TrackerData {
@@ -138,6 +208,79 @@ pub enum TrackerData {
     },
 }
 
+// The two styles are told apart by their fields, which are all read in one pass over the entry.
+// This avoids `#[serde(untagged)]` and `#[serde(flatten)]`, which buffer the entry (or the fields
+// they don't know) and hide the shape of nested tables (such as `bins`) from the deserializer.
+impl<'de> Deserialize<'de> for TrackerData {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(field_identifier, rename_all = "snake_case")]
+        enum Field {
+            Stats,
+            Top5,
+            Sketch,
+            Calls,
+            Commits,
+            #[serde(rename = "officialTime")]
+            OfficialTime,
+            Dependent,
+            TotalTime,
+            #[serde(other)]
+            Other,
+        }
+
+        struct TrackerVisitor;
+
+        impl<'de> Visitor<'de> for TrackerVisitor {
+            type Value = TrackerData;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("tracker data")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let (mut stats, mut top5, mut sketch) = (None, None, None);
+                let (mut calls, mut commits, mut total_time) = (None, None, None);
+                let (mut official_time, mut dependent) = (None, None);
+                while let Some(field) = map.next_key()? {
+                    match field {
+                        Field::Stats => stats = Some(map.next_value()?),
+                        Field::Top5 => top5 = Some(map.next_value()?),
+                        Field::Sketch => sketch = Some(map.next_value()?),
+                        Field::Calls => calls = Some(map.next_value()?),
+                        Field::Commits => commits = Some(map.next_value()?),
+                        Field::OfficialTime => official_time = map.next_value()?,
+                        Field::Dependent => dependent = map.next_value()?,
+                        Field::TotalTime => total_time = Some(map.next_value()?),
+                        Field::Other => {
+                            map.next_value::<IgnoredAny>()?;
+                        }
+                    }
+                }
+
+                let core = TrackerCore {
+                    calls: calls.ok_or_else(|| de::Error::missing_field("calls"))?,
+                    commits: commits.ok_or_else(|| de::Error::missing_field("commits"))?,
+                    official_time,
+                    dependent,
+                    total_time: total_time.ok_or_else(|| de::Error::missing_field("total_time"))?,
+                };
+                match (sketch, stats, top5) {
+                    (Some(sketch), None, None) => Ok(TrackerData::NewStyle { sketch, core }),
+                    (None, Some(stats), Some(top5)) => {
+                        Ok(TrackerData::OldStyle { stats, top5, core })
+                    }
+                    _ => Err(de::Error::custom(
+                        "expected either `sketch`, or both `stats` and `top5`",
+                    )),
+                }
+            }
+        }
+
+        de.deserialize_map(TrackerVisitor)
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema)]
 #[schemars(deny_unknown_fields)]
 pub struct Stats {
@@ -152,10 +295,12 @@ pub struct Stats {
 #[schemars(deny_unknown_fields)]
 pub struct Recording<'a> {
     pub(crate) encounter: Encounter,
+    #[serde(borrow)]
     pub(crate) data: RecordingData<'a>,
 }
 
 #[derive(Debug, PartialEq, Deserialize)]
 pub struct SavedVariables<'a> {
+    #[serde(borrow)]
     pub(crate) recordings: Vec<Recording<'a>>,
 }